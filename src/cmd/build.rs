@@ -1,38 +1,630 @@
-use crate::{Arguments, types::Amalgam};
-use anyhow::Error;
+use crate::{
+    Arguments, Commands,
+    config::{
+        get_product_destination, get_product_name, get_product_taggroups, get_source_urls_by_tags,
+    },
+    document::Document,
+    input::InputFormat,
+    output::{Group, get_writer, write_groups, write_hosts},
+    types::{Amalgam, Domain, Domains, ProductStats, RedirectPolicy, Tags, covered_subdomains},
+    utils::canonical_domain,
+};
+use anyhow::{Error, bail};
+use num_format::{Locale, ToFormattedString};
 use std::boxed::Box;
-use std::fs::File;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File};
 use std::io::{self, Write};
+use std::path::Path;
+
+/// The product statistics, in JSON, written to the build root.
+pub const SUMMARY_JSON: &str = "rh-stats.json";
+/// The product statistics, in Markdown, written to the build root.
+pub const SUMMARY_MARKDOWN: &str = "rh-stats.md";
 
 // Build command implementation
 // This is all very experimental right now.
 pub async fn execute(args: Arguments) -> Result<(), Error> {
+    if args.verbose {
+        println!("Handled by 'build'.");
+    }
+    if let Some(Commands::Build {
+        all_combinations: true,
+        root,
+        max_change,
+        max_change_domains,
+        warn_only,
+        ..
+    }) = &args.command
+    {
+        let limit = ChangeLimit {
+            percent: *max_change,
+            domains: *max_change_domains,
+            warn_only: *warn_only,
+        };
+        return build_all_combinations(&args, root, &limit).await;
+    }
+
+    let amalgam = Amalgam::with_args(vec![args.mainhosts.clone()], args.clone()).await?;
     // Choose output: file or stdout
     let mut writer: Box<dyn Write> = if let Some(ref path) = args.output {
         Box::new(File::create(path)?)
     } else {
         Box::new(io::stdout())
     };
+    write_groups(
+        &mut writer,
+        &args,
+        &amalgam.front_matter,
+        &grouped_domains(&amalgam, ordered_domains(&args, &amalgam)),
+    )?;
 
-    if args.verbose {
-        println!("Handled by 'build'.");
-    }
-    let amalgam = Amalgam::new(vec![args.mainhosts]).await;
+    Ok(())
+}
 
-    if args.domains_sort {
-        let sorteddomains = amalgam.sorteddomains();
-        for domain in sorteddomains {
-            if args.plain_output {
-                writeln!(writer, "{}", domain)?;
-            } else {
-                writeln!(writer, "{} {}", args.iplocalhost, domain)?;
+/// The domains of a single hosts source, grouped under the section comments
+/// which introduce them there, and in their given order within each section.
+/// Other sources, and amalgams of several, make a single group.
+fn grouped_domains(amalgam: &Amalgam, domains: Vec<Domain>) -> Vec<Group> {
+    let source = match amalgam.sources.as_slice() {
+        [source] if source.format == InputFormat::Hosts => source,
+        _ => {
+            return vec![Group {
+                domains,
+                ..Default::default()
+            }];
+        }
+    };
+    let document = Document::parse(&source.raw_list);
+    // a domain listed in several sections goes in the first one
+    let mut section_of: HashMap<Domain, usize> = HashMap::new();
+    for (i, section) in document.sections.iter().enumerate() {
+        for domain in section.entries.iter().flat_map(|e| &e.domains) {
+            if let Some(domain) = canonical_domain(domain) {
+                section_of.entry(domain).or_insert(i);
             }
         }
+    }
+    let mut groups: Vec<Group> = document
+        .sections
+        .into_iter()
+        .map(|section| Group {
+            blank_before: section.blank_before,
+            comments: section.comments,
+            domains: vec![],
+        })
+        .collect();
+    let mut rest = Group::default();
+    for domain in domains {
+        match section_of.get(&domain) {
+            Some(&i) => groups[i].domains.push(domain),
+            None => rest.domains.push(domain),
+        }
+    }
+    groups.push(rest);
+    groups
+}
+
+/// The statistics of a freshly built product, with its change in size since
+/// the previous build.
+fn product_stats(
+    previous: &BTreeMap<String, ProductStats>,
+    name: &str,
+    tags: &[String],
+    destination: &str,
+    amalgam: &Amalgam,
+) -> ProductStats {
+    let domains = amalgam.domains.len();
+    let delta = match previous.get(name) {
+        Some(p) => domains as i64 - p.domains as i64,
+        None => 0,
+    };
+    ProductStats {
+        name: name.to_string(),
+        tags: tags.to_vec(),
+        destination: destination.to_string(),
+        domains,
+        delta,
+        cross_duplicates: amalgam.cross_duplicates.len(),
+        sources: amalgam.source_stats(),
+    }
+}
+
+/// How far the domain count of a source or product may move between builds.
+/// Upstream lists occasionally break, turning up empty or doubled.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ChangeLimit {
+    /// The largest change allowed, as a percentage of the previous count.
+    pub percent: Option<f64>,
+    /// The largest change allowed, in domains.
+    pub domains: Option<usize>,
+    /// Warn, rather than fail, when a change exceeds the limit.
+    pub warn_only: bool,
+}
+
+impl ChangeLimit {
+    /// Describe the change from `previous` to `current` when it exceeds
+    /// the limit.
+    pub fn exceeded(&self, previous: usize, current: usize) -> Option<String> {
+        let change = current as i64 - previous as i64;
+        let percent = if previous == 0 {
+            if change == 0 { 0.0 } else { f64::INFINITY }
+        } else {
+            change as f64 * 100.0 / previous as f64
+        };
+        let too_many = self
+            .domains
+            .is_some_and(|limit| change.unsigned_abs() as usize > limit);
+        let too_far = self.percent.is_some_and(|limit| percent.abs() > limit);
+        if too_many || too_far {
+            Some(format!(
+                "{} -> {} domains ({}, {:+.1}%)",
+                previous.to_formatted_string(&Locale::en),
+                current.to_formatted_string(&Locale::en),
+                signed(change),
+                percent
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+/// The changes in a freshly built product, and in its sources, which exceed
+/// the limit, compared with the previous build.
+pub fn regressions(
+    limit: &ChangeLimit,
+    previous: &BTreeMap<String, ProductStats>,
+    product: &ProductStats,
+) -> Vec<String> {
+    let mut found = vec![];
+    let previous_sources: BTreeMap<&str, usize> = previous
+        .values()
+        .flat_map(|p| p.sources.iter())
+        .map(|s| (s.location.as_str(), s.domains))
+        .collect();
+    for source in &product.sources {
+        if let Some(&before) = previous_sources.get(source.location.as_str())
+            && let Some(change) = limit.exceeded(before, source.domains)
+        {
+            found.push(format!("source {}: {}", source.location, change));
+        }
+    }
+    if let Some(before) = previous.get(&product.name)
+        && let Some(change) = limit.exceeded(before.domains, product.domains)
+    {
+        found.push(format!("product {}: {}", product.name, change));
+    }
+    found
+}
+
+/// Warn of, or fail on, changes beyond the limit, before anything is
+/// written.
+fn check_changes(limit: &ChangeLimit, found: &[String]) -> Result<(), Error> {
+    if found.is_empty() {
         return Ok(());
     }
-    for domain in amalgam.domains {
-        writeln!(writer, "{}", domain)?;
+    let report = found.join("\n  ");
+    if limit.warn_only {
+        eprintln!(
+            "Warning: unexpected size changes since the previous build:\n  {}",
+            report
+        );
+        Ok(())
+    } else {
+        bail!(
+            "Unexpected size changes since the previous build, so nothing was written:\n  {}",
+            report
+        );
     }
+}
+
+/// Build a product for every combination of tags, then report the domain
+/// count of each. Every product is checked before any is written: when a
+/// product's size, or its sources' sizes, moved further than `limit` allows
+/// since the previous build, no product, and no summary, is written.
+async fn build_all_combinations(
+    args: &Arguments,
+    root: &str,
+    limit: &ChangeLimit,
+) -> Result<(), Error> {
+    let previous = read_summary(root);
+    let mut products: Vec<(Amalgam, ProductStats)> = vec![];
+    let mut found: Vec<String> = vec![];
+    for tags in get_product_taggroups() {
+        let name = get_product_name(&tags);
+        let destination = get_product_destination(&tags);
+        if args.verbose {
+            println!("Building {}", name);
+        }
+        let amalgam = Amalgam::with_args(get_source_urls_by_tags(&tags), args.clone()).await?;
+        let stats = product_stats(&previous, &name, &tags, &destination, &amalgam);
+        for regression in regressions(limit, &previous, &stats) {
+            // a source shared by several products is reported once
+            if !found.contains(&regression) {
+                found.push(regression);
+            }
+        }
+        products.push((amalgam, stats));
+    }
+    check_changes(limit, &found)?;
+
+    for (amalgam, stats) in &products {
+        let folder = Path::new(root).join(&stats.destination);
+        if args.verbose {
+            println!("Writing {} into {}", stats.name, folder.display());
+        }
+        fs::create_dir_all(&folder)?;
+        let mut writer = File::create(folder.join("hosts"))?;
+        write_product(&mut writer, args, &stats.name, &stats.tags, amalgam)?;
+        if args.verbose {
+            print!("{}", amalgam);
+        }
+    }
+    let built: Vec<ProductStats> = products.into_iter().map(|(_, stats)| stats).collect();
+
+    println!(
+        "{:<6} {:>10} {:>8} {:>10}  Tags",
+        "Name", "Domains", "Delta", "Redundant"
+    );
+    for p in &built {
+        println!(
+            "{:<6} {:>10} {:>8} {:>10}  {}",
+            p.name,
+            p.domains.to_formatted_string(&Locale::en),
+            signed(p.delta),
+            p.cross_duplicates.to_formatted_string(&Locale::en),
+            p.tags.join(", ")
+        );
+    }
+    write_summary(root, &built)?;
+    Ok(())
+}
+
+/// Read the product statistics of the previous build, keyed by product name.
+fn read_summary(root: &str) -> BTreeMap<String, ProductStats> {
+    fs::read_to_string(Path::new(root).join(SUMMARY_JSON))
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
 
+/// Write the product statistics, in JSON and in Markdown, to the build root.
+fn write_summary(root: &str, products: &[ProductStats]) -> Result<(), Error> {
+    let keyed: BTreeMap<&str, &ProductStats> =
+        products.iter().map(|p| (p.name.as_str(), p)).collect();
+    fs::write(
+        Path::new(root).join(SUMMARY_JSON),
+        serde_json::to_string_pretty(&keyed)?,
+    )?;
+    fs::write(
+        Path::new(root).join(SUMMARY_MARKDOWN),
+        summary_markdown(products),
+    )?;
     Ok(())
 }
+
+/// Markdown tables of products and of the sources they draw from.
+pub fn summary_markdown(products: &[ProductStats]) -> String {
+    let mut md = String::new();
+    md.push_str("| Product | Tags | Domains | Change |\n");
+    md.push_str("|---------|------|--------:|-------:|\n");
+    for p in products {
+        md.push_str(&format!(
+            "| [{}]({}/hosts) | {} | {} | {} |\n",
+            p.name,
+            p.destination.trim_end_matches('/'),
+            p.tags.join(", "),
+            p.domains.to_formatted_string(&Locale::en),
+            signed(p.delta)
+        ));
+    }
+
+    // each source appears once, whichever products it contributes to
+    let mut sources: BTreeMap<&str, usize> = BTreeMap::new();
+    for p in products {
+        for s in &p.sources {
+            sources.insert(s.location.as_str(), s.domains);
+        }
+    }
+    md.push_str("\n| Source | Domains |\n");
+    md.push_str("|--------|--------:|\n");
+    for (location, domains) in sources {
+        md.push_str(&format!(
+            "| {} | {} |\n",
+            location,
+            domains.to_formatted_string(&Locale::en)
+        ));
+    }
+    md
+}
+
+/// A count with an explicit sign, like `+1,234` or `-56`.
+fn signed(n: i64) -> String {
+    let formatted = n.unsigned_abs().to_formatted_string(&Locale::en);
+    match n.signum() {
+        1 => format!("+{}", formatted),
+        -1 => format!("-{}", formatted),
+        _ => formatted,
+    }
+}
+
+/// Write a product's hosts file.
+fn write_product(
+    writer: &mut dyn Write,
+    args: &Arguments,
+    name: &str,
+    tags: &Tags,
+    amalgam: &Amalgam,
+) -> Result<(), Error> {
+    let header = vec![
+        format!("Product: {}", name),
+        format!("Tags: {}", tags.join(", ")),
+        format!(
+            "Number of unique domains: {}",
+            amalgam.domains.len().to_formatted_string(&Locale::en)
+        ),
+    ];
+    write_hosts(writer, args, &header, &ordered_domains(args, amalgam))?;
+    Ok(())
+}
+
+/// The amalgam's domains, sorted when `--sort` is given, less redirecting
+/// entries when `--redirect-policy reject` is given, and less subdomains of
+/// blocked domains when `--prune` is given and the output format allows.
+fn ordered_domains(args: &Arguments, amalgam: &Amalgam) -> Vec<Domain> {
+    let mut domains: Vec<Domain> = if args.domains_sort {
+        amalgam.sorteddomains()
+    } else {
+        amalgam.domains.iter().cloned().collect()
+    };
+    let redirects: HashSet<Domain> = amalgam.redirects().into_iter().map(|h| h.domain).collect();
+    if !redirects.is_empty() {
+        match args.redirect_policy {
+            RedirectPolicy::Rewrite => {
+                // kept, they are written behind the null route like every
+                // other domain, and their own addresses are dropped
+                eprintln!(
+                    "Rewriting {} entries that redirect to non-null addresses to {}.",
+                    redirects.len().to_formatted_string(&Locale::en),
+                    args.iplocalhost
+                );
+            }
+            RedirectPolicy::Reject => {
+                eprintln!(
+                    "Rejecting {} entries that redirect to non-null addresses.",
+                    redirects.len().to_formatted_string(&Locale::en)
+                );
+                domains.retain(|d| !redirects.contains(d));
+            }
+        }
+    }
+    if args.prune {
+        // only parents which survived the redirect policy cover anything
+        let covered = covered_subdomains(&domains.iter().cloned().collect::<Domains>());
+        if get_writer(args).blocks_subdomains() {
+            domains.retain(|d| !covered.contains(d));
+            eprintln!(
+                "Pruned {} subdomains covered by a blocked parent.",
+                covered.len().to_formatted_string(&Locale::en)
+            );
+        } else {
+            eprintln!(
+                "{} subdomains are covered by a blocked parent, but this output format cannot block subdomains, so none were pruned.",
+                covered.len().to_formatted_string(&Locale::en)
+            );
+        }
+    }
+    domains
+}
+
+#[async_std::test]
+async fn test_redirect_policy_rewrites_or_rejects_redirecting_entries() {
+    let amalgam = Amalgam::new(vec![
+        "1.2.3.4 redirect.example.com\n0.0.0.0 blocked.example.com\n255.255.255.255 blackhole.example.com",
+    ])
+    .await.unwrap();
+    let built = |redirect_policy| {
+        let args = Arguments {
+            iplocalhost: "0.0.0.0".to_string(),
+            skipheaders: true,
+            redirect_policy,
+            ..Default::default()
+        };
+        let mut out: Vec<u8> = vec![];
+        write_hosts(&mut out, &args, &[], &ordered_domains(&args, &amalgam)).unwrap();
+        String::from_utf8(out).unwrap()
+    };
+    let rewritten = built(RedirectPolicy::Rewrite);
+    assert!(
+        rewritten.contains("0.0.0.0 redirect.example.com"),
+        "{}",
+        rewritten
+    );
+    assert!(!rewritten.contains("1.2.3.4"), "{}", rewritten);
+    let rejected = built(RedirectPolicy::Reject);
+    assert!(!rejected.contains("redirect.example.com"), "{}", rejected);
+    // the broadcast blackhole is a null route, not a redirect
+    assert!(
+        rejected.contains("0.0.0.0 blackhole.example.com"),
+        "{}",
+        rejected
+    );
+}
+
+#[async_std::test]
+async fn test_prune_keeps_subdomains_of_rejected_parents() {
+    let amalgam = Amalgam::new(vec![
+        "1.2.3.4 example.com\n0.0.0.0 ads.example.com\n0.0.0.0 tracker.net\n0.0.0.0 cdn.tracker.net",
+    ])
+    .await.unwrap();
+    let args = Arguments {
+        prune: true,
+        redirect_policy: RedirectPolicy::Reject,
+        format: crate::output::OutputFormat::Adblock,
+        ..Default::default()
+    };
+    // example.com is rejected, so it no longer covers ads.example.com
+    assert_eq!(
+        ordered_domains(&args, &amalgam),
+        vec!["ads.example.com".to_string(), "tracker.net".to_string()]
+    );
+}
+
+#[async_std::test]
+async fn test_single_hosts_source_keeps_its_section_comments() {
+    let amalgam = Amalgam::new(vec![
+        "# Title: test\n\n# Ads\n0.0.0.0 ads.example.com\n0.0.0.0 Ads2.example.com\n\n# Redirects\n1.2.3.4 redirect.example.com\n# Trackers\n0.0.0.0 t.example.org 0.0.0.0 ads.example.com",
+    ])
+    .await
+    .unwrap();
+    let args = Arguments {
+        iplocalhost: "0.0.0.0".to_string(),
+        redirect_policy: RedirectPolicy::Reject,
+        ..Default::default()
+    };
+    let mut out: Vec<u8> = vec![];
+    write_groups(
+        &mut out,
+        &args,
+        &amalgam.front_matter,
+        &grouped_domains(&amalgam, ordered_domains(&args, &amalgam)),
+    )
+    .unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "# Title: test\n\n# Ads\n0.0.0.0 ads.example.com\n0.0.0.0 ads2.example.com\n# Trackers\n0.0.0.0 t.example.org\n"
+    );
+
+    // an amalgam of several sources has no sections
+    let amalgam = Amalgam::new(vec!["# Ads\n0.0.0.0 a.com", "# More\n0.0.0.0 b.com"])
+        .await
+        .unwrap();
+    let groups = grouped_domains(&amalgam, ordered_domains(&args, &amalgam));
+    assert_eq!(groups.len(), 1);
+    assert!(groups[0].comments.is_empty());
+}
+
+#[test]
+fn test_change_limit_flags_changes_by_percentage_or_domains() {
+    let limit = ChangeLimit {
+        percent: Some(10.0),
+        domains: Some(500),
+        ..Default::default()
+    };
+    assert_eq!(limit.exceeded(1_000, 1_050), None);
+    assert_eq!(
+        limit.exceeded(1_000, 800),
+        Some("1,000 -> 800 domains (-200, -20.0%)".to_string())
+    );
+    // a small relative change, but a big one in domains
+    assert!(limit.exceeded(100_000, 109_000).is_some());
+    assert!(limit.exceeded(0, 10).is_some());
+    assert_eq!(ChangeLimit::default().exceeded(1_000, 0), None);
+}
+
+#[test]
+fn test_regressions_name_the_offending_source_and_product() {
+    use crate::types::SourceStats;
+    let stats = |domains_a: usize, domains_b: usize| ProductStats {
+        name: "base".to_string(),
+        domains: domains_a + domains_b,
+        sources: vec![
+            SourceStats {
+                location: "https://a.example/hosts".to_string(),
+                domains: domains_a,
+                ..Default::default()
+            },
+            SourceStats {
+                location: "https://b.example/hosts".to_string(),
+                domains: domains_b,
+                ..Default::default()
+            },
+        ],
+        ..Default::default()
+    };
+    let previous = BTreeMap::from([("base".to_string(), stats(1_000, 9_000))]);
+    let limit = ChangeLimit {
+        percent: Some(50.0),
+        ..Default::default()
+    };
+    assert!(regressions(&limit, &previous, &stats(900, 9_500)).is_empty());
+    assert_eq!(
+        regressions(&limit, &previous, &stats(0, 9_000)),
+        vec!["source https://a.example/hosts: 1,000 -> 0 domains (-1,000, -100.0%)".to_string()]
+    );
+    let limit = ChangeLimit {
+        percent: Some(5.0),
+        ..Default::default()
+    };
+    assert_eq!(
+        regressions(&limit, &previous, &stats(0, 9_000))[1],
+        "product base: 10,000 -> 9,000 domains (-1,000, -10.0%)"
+    );
+}
+
+#[test]
+fn test_change_limits_apply_only_to_all_combinations() {
+    use clap::Parser;
+    assert!(Arguments::try_parse_from(["rh", "build", "--max-change", "10"]).is_err());
+    assert!(Arguments::try_parse_from(["rh", "build", "--warn-only"]).is_err());
+    assert!(
+        Arguments::try_parse_from(["rh", "build", "--all-combinations", "--max-change", "10"])
+            .is_ok()
+    );
+}
+
+#[test]
+fn test_check_changes_fails_or_warns() {
+    let found = vec!["product base: 10,000 -> 9,000 domains (-1,000, -10.0%)".to_string()];
+    let error = check_changes(&ChangeLimit::default(), &found).unwrap_err();
+    assert!(
+        error.to_string().contains("nothing was written"),
+        "{}",
+        error
+    );
+    let warn_only = ChangeLimit {
+        warn_only: true,
+        ..Default::default()
+    };
+    assert!(check_changes(&warn_only, &found).is_ok());
+    assert!(check_changes(&ChangeLimit::default(), &[]).is_ok());
+}
+
+#[test]
+fn test_summary_markdown_lists_products_and_sources() {
+    use crate::types::SourceStats;
+    let source = SourceStats {
+        location: "https://example.com/hosts".to_string(),
+        domains: 1_000,
+        ..Default::default()
+    };
+    let products = vec![
+        ProductStats {
+            name: "base".to_string(),
+            tags: vec!["base".to_string()],
+            destination: "./".to_string(),
+            domains: 1_000,
+            delta: 12,
+            cross_duplicates: 0,
+            sources: vec![source.clone()],
+        },
+        ProductStats {
+            name: "p".to_string(),
+            tags: vec!["base".to_string(), "porn".to_string()],
+            destination: "./alternates/porn".to_string(),
+            domains: 2_500,
+            delta: -3,
+            cross_duplicates: 250,
+            sources: vec![source],
+        },
+    ];
+    let md = summary_markdown(&products);
+    assert!(md.contains("| [base](./hosts) | base | 1,000 | +12 |"));
+    assert!(md.contains("| [p](./alternates/porn/hosts) | base, porn | 2,500 | -3 |"));
+    assert_eq!(
+        md.matches("https://example.com/hosts").count(),
+        1,
+        "Expected each source to be listed once"
+    );
+}
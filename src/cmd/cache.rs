@@ -2,13 +2,13 @@
 //!
 
 // #![allow(dead_code)]
-use crate::{Arguments, Commands, config::get_shortcuts, types::Hostssource, utils::hash};
+use crate::{
+    Arguments, Commands, compression, config::get_shortcuts, fetch, types::Hostssource, utils::hash,
+};
 use anyhow::Context;
 use anyhow::{anyhow, bail};
 use async_std::println;
 use clap::Subcommand;
-use directories::ProjectDirs;
-use futures::executor::block_on;
 use std::{
     fs::{self, File},
     io::prelude::*,
@@ -64,15 +64,20 @@ pub async fn get(s: String) -> Option<PathBuf> {
     if pb.is_file() { Some(pb) } else { None }
 }
 
-/// Set cached item in the application cache.
-pub async fn set(file: String, body: String) -> anyhow::Result<()> {
+/// Set cached item in the application cache, gzipped when `compress` is true.
+pub async fn set(file: String, body: String, compress: bool) -> anyhow::Result<()> {
     let mut output = File::create(
         get_cache_dir()
             .await
             .join(get_cache_key(Cacheable::String(file))),
     )
     .expect("Unable to cache HTTP request result.");
-    if write!(output, "{}", body).is_ok() {
+    let bytes = if compress {
+        compression::compress(&body)?
+    } else {
+        body.into_bytes()
+    };
+    if output.write_all(&bytes).is_ok() {
         Ok(())
     } else {
         Err(anyhow!("Unable to cache HTTP request result."))
@@ -147,11 +152,19 @@ pub(crate) async fn prime(args: Arguments) -> anyhow::Result<()> {
         .context("unable to delete cache".to_string())?;
     let mut shortcuts: Vec<String> = get_shortcuts().into_values().collect();
     shortcuts.dedup();
+    let fetcher = fetch::fetcher(&args)?;
     for shortcut in shortcuts {
         if args.verbose {
             println!("Priming {}", shortcut.to_owned()).await;
         }
-        block_on(Hostssource::new(shortcut.to_owned(), shortcut.to_owned()));
+        let mut source = Hostssource {
+            args: args.clone(),
+            ..Default::default()
+        };
+        // one source which does not load should not keep the others out of the cache
+        if let Err(e) = source.load_with(&shortcut, Some(fetcher.as_ref())).await {
+            eprintln!("Warning: unable to prime {}: {:#}", shortcut, e);
+        }
     }
     Ok(())
 }
@@ -168,8 +181,8 @@ async fn report(args: Arguments) -> anyhow::Result<()> {
 
 /// Returns the cache folder following the user's OS conventions.
 pub async fn get_cache_dir() -> PathBuf {
-    let proj_dirs = ProjectDirs::from("", "", "rh").unwrap();
-    let cache_dir = proj_dirs.cache_dir();
+    let cache_dir = cache_root();
+    let cache_dir = cache_dir.as_path();
     if !cache_dir.exists() {
         // create the folder if it does not exists
         let create_dir_result: Result<(), std::io::Error> = fs::create_dir_all(cache_dir);
@@ -183,6 +196,18 @@ pub async fn get_cache_dir() -> PathBuf {
     cache_dir.to_owned()
 }
 
+#[cfg(not(test))]
+fn cache_root() -> PathBuf {
+    let proj_dirs = directories::ProjectDirs::from("", "", "rh").unwrap();
+    proj_dirs.cache_dir().to_owned()
+}
+
+/// Tests cache in a folder of their own, away from the user's cache.
+#[cfg(test)]
+fn cache_root() -> PathBuf {
+    std::env::temp_dir().join(format!("rh-test-cache-{}", std::process::id()))
+}
+
 /// Returns the hashed cache key.
 pub fn get_cache_key(s: Cacheable) -> String {
     match s {
@@ -1,8 +1,8 @@
 use crate::Arguments;
-use crate::types::{Comparable, Hostssource};
+use crate::types::{Comparable, Hostssource, STDIN};
 /// Core behavior for the application
 ///
-use anyhow::Error;
+use anyhow::{Error, bail};
 use arboard::Clipboard;
 use futures::executor::block_on;
 
@@ -12,13 +12,16 @@ pub fn execute(args: Arguments) -> Result<(), Error> {
         println!("Handled by 'core'.");
     }
 
+    if args.mainhosts == STDIN && args.comparehosts.as_deref() == Some(STDIN) {
+        bail!("Only one of the main and compare hosts can be read from stdin.");
+    }
+
     // step 1: load the mainhosts
     let mut mainhosts = Hostssource {
         args: args.clone(),
         ..Default::default()
     };
-    // ignore the result of this load for now
-    _ = block_on(mainhosts.load(&args.mainhosts));
+    block_on(mainhosts.load(&args.mainhosts))?;
 
     if args.isolate.is_some() {
         // handle the hosts list isolation here.
@@ -33,8 +36,7 @@ pub fn execute(args: Arguments) -> Result<(), Error> {
             args: args.clone(),
             ..Default::default()
         };
-        // ignore the result of this load for now
-        _ = block_on(comparisonhosts.load(&clipboard_text));
+        block_on(comparisonhosts.load(&clipboard_text))?;
 
         // now, compare the two
         mainhosts.compare(Box::new(comparisonhosts));
@@ -43,11 +45,15 @@ pub fn execute(args: Arguments) -> Result<(), Error> {
             args: args.clone(),
             ..Default::default()
         };
-        // ignore the result of this load for now
-        _ = block_on(comparisonhosts.load(&args.comparehosts.unwrap()));
+        block_on(comparisonhosts.load(&args.comparehosts.unwrap()))?;
 
         // now, compare the two
         mainhosts.compare(Box::new(comparisonhosts));
+    } else if args.json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&mainhosts.report_json())?
+        );
     } else {
         println!("{}", mainhosts);
     }
@@ -66,3 +72,24 @@ pub fn info(args: Arguments) {
 
 #[test]
 fn this_test_always_passes() {}
+
+#[test]
+fn test_core_reports_a_source_which_does_not_load() {
+    let args = Arguments {
+        base_url: Some(crate::fetch::FIXTURES.to_string()),
+        mainhosts: "https://example.com/error-page".to_string(),
+        ..Default::default()
+    };
+    let error = execute(args).unwrap_err();
+    assert!(error.to_string().contains("web page"), "{}", error);
+}
+
+#[test]
+fn test_core_reports_a_missing_file() {
+    let args = Arguments {
+        mainhosts: "/nonexistent/hosts".to_string(),
+        ..Default::default()
+    };
+    let error = execute(args).unwrap_err();
+    assert!(error.to_string().contains("does not exist"), "{}", error);
+}
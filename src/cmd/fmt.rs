@@ -0,0 +1,227 @@
+//! Rewrite hosts files canonically, in place.
+//!
+use crate::{
+    Arguments, Commands,
+    compression::{Compression, detect},
+    config::get_shortcuts,
+    document::{Document, Entry, Section},
+    input::InputFormat,
+    types::{Domain, HEADER_TOKENS, Hostssource, domain_order},
+    utils::{canonical_domain, is_domain},
+};
+use anyhow::{Context, Error, bail};
+use num_format::{Locale, ToFormattedString};
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+
+/// What formatting a hosts file changed.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FmtStats {
+    pub duplicates: usize,
+    pub invalids: usize,
+}
+
+// Fmt command implementation
+pub async fn execute(args: Arguments) -> Result<(), Error> {
+    let Some(Commands::Fmt { file }) = &args.command else {
+        bail!("The fmt command requires a file.");
+    };
+    if args.verbose {
+        println!("Handled by 'fmt'.");
+    }
+    // fmt rewrites the file it reads, so it reads only plain local files
+    if file == "-" {
+        bail!("fmt rewrites a file in place, and cannot format stdin.");
+    }
+    if file.contains("://") {
+        bail!(
+            "fmt rewrites a file in place, and cannot format the URL {}.",
+            file
+        );
+    }
+    if get_shortcuts().contains_key(file) && !Path::new(file).exists() {
+        bail!(
+            "{} is a shortcut, not a file. fmt rewrites a file in place.",
+            file
+        );
+    }
+    let bytes = fs::read(file).with_context(|| format!("Unable to read {}.", file))?;
+    if detect(&bytes, None) != Compression::None {
+        bail!(
+            "{} is compressed. Decompress it before formatting it.",
+            file
+        );
+    }
+    let text = String::from_utf8(bytes).with_context(|| format!("{} is not UTF-8 text.", file))?;
+    let mut source = Hostssource {
+        args: args.clone(),
+        location: file.clone(),
+        ..Default::default()
+    };
+    source.parse(&text);
+    // only a hosts file can be written back as one
+    if source.format != InputFormat::Hosts {
+        bail!(
+            "{} looks like {} input, not a hosts file. fmt formats hosts files only.",
+            file,
+            format!("{:?}", source.format).to_lowercase()
+        );
+    }
+
+    let (document, stats) =
+        format_document(source.document(), &args.iplocalhost, args.domains_sort);
+    let lines = document.render();
+    let mut text = lines.join("\n");
+    text.push('\n');
+    fs::write(file, text)?;
+    println!(
+        "Formatted {}: removed {} duplicates and {} invalid entries.",
+        file,
+        stats.duplicates.to_formatted_string(&Locale::en),
+        stats.invalids.to_formatted_string(&Locale::en)
+    );
+    Ok(())
+}
+
+/// Format a hosts file document.
+///
+/// Comments stay where they are, default entries like `127.0.0.1 localhost`
+/// stay as they are, and every other entry becomes one canonical domain per
+/// line behind `ip`, keeping its inline comment. Duplicates and invalid
+/// domains are dropped; the comment of an entry with no valid domain at all
+/// stays behind as a comment line. With `sort` the entries of each section
+/// are sorted.
+pub fn format_document(mut document: Document, ip: &str, sort: bool) -> (Document, FmtStats) {
+    let mut stats = FmtStats::default();
+    let mut seen: HashSet<Domain> = HashSet::new();
+    let mut seen_defaults: HashSet<String> = HashSet::new();
+    let is_default = |e: &str| HEADER_TOKENS.contains(&e) || e.parse::<IpAddr>().is_ok();
+
+    let mut sections: Vec<Section> = vec![];
+    for mut section in std::mem::take(&mut document.sections) {
+        let mut entries: Vec<Entry> = vec![];
+        for mut entry in std::mem::take(&mut section.entries) {
+            if entry.domains.iter().all(|d| is_default(d)) {
+                let key = format!("{} {}", entry.ip_address, entry.domains.join(" "));
+                if seen_defaults.insert(key) {
+                    entries.push(entry);
+                } else {
+                    stats.duplicates += 1;
+                }
+                continue;
+            }
+            let has_domain = entry.domains.iter().any(|e| {
+                !HEADER_TOKENS.contains(&e.as_str())
+                    && canonical_domain(e).is_some_and(|d| is_domain(&d))
+            });
+            if !has_domain && let Some(comment) = entry.comment.take() {
+                // the comment outlives its entry, as a line of its own which
+                // starts a section
+                section.entries = std::mem::take(&mut entries);
+                if sort {
+                    sort_entries(&mut section.entries);
+                }
+                sections.push(section);
+                section = Section {
+                    comments: vec![format!("# {}", comment)],
+                    ..Default::default()
+                };
+            }
+            for element in &entry.domains {
+                if HEADER_TOKENS.contains(&element.as_str()) {
+                    continue;
+                }
+                match canonical_domain(element) {
+                    Some(domain) if is_domain(&domain) => {
+                        if seen.insert(domain.clone()) {
+                            entries.push(Entry {
+                                line: entry.line,
+                                ip_address: ip.to_string(),
+                                domains: vec![domain],
+                                // the comment stays with the first domain
+                                comment: entry.comment.take(),
+                            });
+                        } else {
+                            stats.duplicates += 1;
+                        }
+                    }
+                    _ => stats.invalids += 1,
+                }
+            }
+        }
+        if sort {
+            sort_entries(&mut entries);
+        }
+        section.entries = entries;
+        sections.push(section);
+    }
+    sections.retain(|s| !s.comments.is_empty() || !s.entries.is_empty());
+    document.sections = sections;
+    (document, stats)
+}
+
+/// Sort entries: defaults first, as they were, then the domains in order.
+fn sort_entries(entries: &mut [Entry]) {
+    let is_default = |e: &str| HEADER_TOKENS.contains(&e) || e.parse::<IpAddr>().is_ok();
+    entries.sort_by(|a, b| {
+        let a_default = a.domains.iter().all(|d| is_default(d));
+        let b_default = b.domains.iter().all(|d| is_default(d));
+        b_default.cmp(&a_default).then_with(|| {
+            if a_default {
+                Ordering::Equal
+            } else {
+                domain_order(&a.domains[0], &b.domains[0])
+            }
+        })
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.lines().map(|l| l.to_string()).collect()
+    }
+
+    fn format_lines(text: &str, sort: bool) -> (Vec<String>, FmtStats) {
+        let (document, stats) = format_document(Document::parse(&lines(text)), "0.0.0.0", sort);
+        (document.render(), stats)
+    }
+
+    #[test]
+    fn test_format_lines_keeps_comments_and_canonicalizes_entries() {
+        let (formatted, stats) = format_lines(
+            "# Title: test\n#\n127.0.0.1  localhost\n\n\n# Ads\n127.0.0.1\tAds.Example.com  # trailing\n0.0.0.0 example.com www.example.com ads..example.com\n0.0.0.0 intranet # fix me\n1.2.3.4 bad_domain\n0.0.0.0 bad.example.com\n# Trackers\n0.0.0.0 example.com\n0.0.0.0 tracker.example.org\n\n",
+            false,
+        );
+        assert_eq!(
+            formatted,
+            lines(
+                "# Title: test\n#\n127.0.0.1 localhost\n\n# Ads\n0.0.0.0 ads.example.com # trailing\n0.0.0.0 example.com\n0.0.0.0 www.example.com\n# fix me\n0.0.0.0 bad.example.com\n# Trackers\n0.0.0.0 tracker.example.org"
+            )
+        );
+        assert_eq!(
+            stats,
+            FmtStats {
+                duplicates: 1,
+                invalids: 3
+            }
+        );
+    }
+
+    #[test]
+    fn test_format_lines_sorts_within_sections() {
+        let (formatted, _) = format_lines(
+            "# one\n0.0.0.0 b.com\n0.0.0.0 a.com\n# two\n0.0.0.0 d.com\n0.0.0.0 c.com",
+            true,
+        );
+        assert_eq!(
+            formatted,
+            lines("# one\n0.0.0.0 a.com\n0.0.0.0 b.com\n# two\n0.0.0.0 c.com\n0.0.0.0 d.com")
+        );
+    }
+}
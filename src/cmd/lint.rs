@@ -0,0 +1,275 @@
+//! Lint hosts files, like those contributed upstream.
+//!
+use crate::{
+    Arguments, Commands,
+    types::{Domain, HEADER_TOKENS, Hostssource},
+    utils::{canonical_domain, norm_string, trim_inline_comments},
+};
+use anyhow::{Context, Error, bail};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::net::IpAddr;
+
+/// How serious a lint finding is. Only errors fail the lint.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// Something wrong with a line of a hosts file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Finding {
+    /// The 1-based line number, or 0 for the file as a whole.
+    pub line: usize,
+    pub severity: Severity,
+    pub message: String,
+}
+
+// Lint command implementation
+pub async fn execute(args: Arguments) -> Result<(), Error> {
+    let Some(Commands::Lint { file }) = &args.command else {
+        bail!("The lint command requires a file.");
+    };
+    if args.verbose {
+        println!("Handled by 'lint'.");
+    }
+    let text = fs::read_to_string(file).with_context(|| format!("Unable to read {}.", file))?;
+    let mut source = Hostssource {
+        args: args.clone(),
+        location: file.clone(),
+        ..Default::default()
+    };
+    source.parse(&text);
+
+    let findings = lint(&text, &source);
+    for finding in &findings {
+        match finding.line {
+            0 => println!("{}: {}: {}", file, finding.severity, finding.message),
+            n => println!("{}:{}: {}: {}", file, n, finding.severity, finding.message),
+        }
+    }
+    let errors = findings
+        .iter()
+        .filter(|f| f.severity == Severity::Error)
+        .count();
+    let warnings = findings.len() - errors;
+    println!("{}: {} errors, {} warnings", file, errors, warnings);
+    if errors > 0 {
+        bail!("{} failed linting with {} errors.", file, errors);
+    }
+    Ok(())
+}
+
+/// Lint the text of a hosts file, given the source loaded from it.
+pub fn lint(text: &str, source: &Hostssource) -> Vec<Finding> {
+    let mut findings: Vec<Finding> = vec![];
+    let mut crlf: Vec<usize> = vec![];
+    let mut first_seen: HashMap<Domain, usize> = HashMap::new();
+    // the address used by each line which blocks something
+    let mut addresses: Vec<(usize, String)> = vec![];
+
+    let lines: Vec<&str> = text.split('\n').collect();
+    // a final newline leaves an empty last element, which is not a line
+    let count = if text.ends_with('\n') {
+        lines.len() - 1
+    } else {
+        lines.len()
+    };
+    for (i, raw) in lines.iter().take(count).enumerate() {
+        let n = i + 1;
+        let line = match raw.strip_suffix('\r') {
+            Some(line) => {
+                crlf.push(n);
+                line
+            }
+            None => raw,
+        };
+        if line.ends_with([' ', '\t']) {
+            findings.push(warning(n, "trailing whitespace"));
+        }
+        if let Some((column, c)) = line.chars().enumerate().find(|(_, c)| !c.is_ascii()) {
+            findings.push(error(
+                n,
+                format!(
+                    "non-ASCII character '{}' at column {}; use punycode for internationalized domains",
+                    c,
+                    column + 1
+                ),
+            ));
+        }
+
+        let normalized = trim_inline_comments(norm_string(line));
+        if normalized.is_empty() || normalized.starts_with('#') {
+            continue;
+        }
+        let mut elements: Vec<&str> = normalized.split_whitespace().collect();
+        let ip_address = if elements.len() > 1 && elements[0].parse::<IpAddr>().is_ok() {
+            elements.remove(0)
+        } else {
+            ""
+        };
+        let is_default = |e: &str| HEADER_TOKENS.contains(&e) || e.parse::<IpAddr>().is_ok();
+        let blocks = elements.iter().any(|e| !is_default(e));
+        for element in &elements {
+            if HEADER_TOKENS.contains(element) {
+                // `127.0.0.1 localhost` and the like are expected defaults
+                if blocks || ip_address.is_empty() || ip_address == "0.0.0.0" {
+                    findings.push(warning(
+                        n,
+                        format!(
+                            "'{}' is shadowed by the default entries and will be ignored",
+                            element
+                        ),
+                    ));
+                }
+                continue;
+            }
+            let Some(domain) = canonical_domain(element) else {
+                continue;
+            };
+            if let Some(first) = first_seen.get(&domain) {
+                findings.push(error(
+                    n,
+                    format!("duplicate '{}', first seen on line {}", domain, first),
+                ));
+            } else {
+                first_seen.insert(domain, n);
+            }
+        }
+        if blocks {
+            addresses.push((n, ip_address.to_string()));
+        }
+    }
+
+    for invalid in &source.invalid_entries {
+        findings.push(error(
+            invalid.line,
+            format!("invalid domain '{}': {}", invalid.entry, invalid.reason),
+        ));
+    }
+
+    // every blocking line should use the address most of them use
+    let mut tally: Vec<(&str, usize)> = vec![];
+    for (_, address) in &addresses {
+        match tally.iter_mut().find(|(a, _)| a == address) {
+            Some((_, count)) => *count += 1,
+            None => tally.push((address, 1)),
+        }
+    }
+    if let Some((common, _)) = tally.iter().max_by_key(|(_, count)| *count)
+        && tally.len() > 1
+    {
+        for (n, address) in &addresses {
+            if address != common {
+                findings.push(error(
+                    *n,
+                    format!(
+                        "mixed addresses: '{}' where most entries use '{}'",
+                        display_address(address),
+                        display_address(common)
+                    ),
+                ));
+            }
+        }
+    }
+
+    if !crlf.is_empty() {
+        findings.push(warning(
+            crlf[0],
+            format!("CRLF line endings on {} lines, starting here", crlf.len()),
+        ));
+    }
+    if !text.is_empty() && !text.ends_with('\n') {
+        findings.push(warning(count, "missing final newline"));
+    }
+
+    findings.sort_by_key(|f| f.line);
+    findings
+}
+
+fn display_address(address: &str) -> &str {
+    match address {
+        "" => "no address",
+        a => a,
+    }
+}
+
+fn error(line: usize, message: impl Into<String>) -> Finding {
+    Finding {
+        line,
+        severity: Severity::Error,
+        message: message.into(),
+    }
+}
+
+fn warning(line: usize, message: impl Into<String>) -> Finding {
+    Finding {
+        line,
+        severity: Severity::Warning,
+        message: message.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lint_text(text: &str) -> Vec<Finding> {
+        let mut source = Hostssource {
+            ..Default::default()
+        };
+        source.parse(text);
+        lint(text, &source)
+    }
+
+    #[test]
+    fn test_lint_clean_file_has_no_findings() {
+        let findings = lint_text(
+            "# test\n127.0.0.1 localhost\n::1 localhost\n0.0.0.0 example.com\n0.0.0.0 example.org\n",
+        );
+        assert!(
+            findings.is_empty(),
+            "Expected no findings, but got: {:?}",
+            findings
+        );
+    }
+
+    #[test]
+    fn test_lint_reports_problems_with_line_numbers() {
+        let findings = lint_text(
+            "# test\n0.0.0.0 example.com \n0.0.0.0 intranet\r\n127.0.0.1 example.org\n0.0.0.0 localhost\n0.0.0.0 bücher.de\n0.0.0.0 Example.com",
+        );
+        let found = |line: usize, text: &str| {
+            findings
+                .iter()
+                .any(|f| f.line == line && f.message.contains(text))
+        };
+        assert!(found(2, "trailing whitespace"), "{:?}", findings);
+        assert!(
+            found(3, "invalid domain 'intranet': no dot"),
+            "{:?}",
+            findings
+        );
+        assert!(found(3, "CRLF line endings on 1 lines"), "{:?}", findings);
+        assert!(found(4, "mixed addresses: '127.0.0.1'"), "{:?}", findings);
+        assert!(found(5, "'localhost' is shadowed"), "{:?}", findings);
+        assert!(found(6, "non-ASCII character 'ü'"), "{:?}", findings);
+        assert!(
+            found(7, "duplicate 'example.com', first seen on line 2"),
+            "{:?}",
+            findings
+        );
+        assert!(found(7, "missing final newline"), "{:?}", findings);
+    }
+}
@@ -2,4 +2,8 @@
 pub mod build;
 pub mod cache;
 pub mod core;
+pub mod fmt;
 pub mod init;
+pub mod lint;
+pub mod overlap;
+pub mod sources;
@@ -0,0 +1,314 @@
+//! Overlap between any number of lists.
+//!
+use crate::{
+    Arguments, Commands,
+    config::{get_shortcuts, get_source_urls_by_tags, get_unique_tags},
+    fetch::{self, Fetcher},
+    output::{ReportFormat, csv_field},
+    types::{Amalgam, Domains, Hostssource, IntersectionPattern},
+};
+use anyhow::{Error, bail};
+use num_format::{Locale, ToFormattedString};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Pairwise overlap between lists, and what each contributes alone.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Overlap {
+    pub lists: Vec<String>,
+    pub domains: Vec<usize>,
+    /// Domains found only in each list.
+    pub unique: Vec<usize>,
+    /// Domains common to each pair of lists.
+    pub intersections: Vec<Vec<usize>>,
+    /// Each pair's intersection over its union.
+    pub jaccard: Vec<Vec<f64>>,
+}
+
+// Overlap command implementation
+pub async fn execute(args: Arguments) -> Result<(), Error> {
+    let Some(Commands::Overlap {
+        lists,
+        report,
+        upset,
+    }) = &args.command
+    else {
+        bail!("The overlap command requires lists.");
+    };
+    if args.verbose {
+        println!("Handled by 'overlap'.");
+    }
+    if lists.len() < 2 {
+        bail!("The overlap command requires at least two lists.");
+    }
+    if *upset {
+        return upset_patterns(&args, lists, *report).await;
+    }
+    let fetcher = fetch::fetcher(&args)?;
+    let mut loaded: Vec<(String, Domains)> = vec![];
+    for list in lists {
+        loaded.push((
+            list.to_string(),
+            load_domains(&args, fetcher.as_ref(), list).await?,
+        ));
+    }
+    let overlap = overlap(&loaded);
+    match report {
+        ReportFormat::Table => print!("{}", overlap_table(&overlap)),
+        ReportFormat::Csv => print!("{}", overlap_csv(&overlap)),
+        ReportFormat::Json => println!("{}", serde_json::to_string_pretty(&overlap)?),
+    }
+    Ok(())
+}
+
+/// Report every intersection pattern across the lists, with tags expanded
+/// into the sources which carry them.
+async fn upset_patterns(
+    args: &Arguments,
+    lists: &[String],
+    report: ReportFormat,
+) -> Result<(), Error> {
+    let mut sources: Vec<String> = vec![];
+    for list in lists {
+        let expanded = if is_tag(list) {
+            get_source_urls_by_tags(&vec![list.to_string()])
+        } else {
+            vec![list.to_string()]
+        };
+        for source in expanded {
+            if !sources.contains(&source) {
+                sources.push(source);
+            }
+        }
+    }
+    let amalgam = Amalgam::with_args(sources.clone(), args.clone()).await?;
+    let patterns = amalgam.intersection_patterns();
+    match report {
+        ReportFormat::Table => {
+            println!("{:>10}  Sources", "Domains");
+            for p in &patterns {
+                println!(
+                    "{:>10}  {}",
+                    p.domains.to_formatted_string(&Locale::en),
+                    p.sources.join(" & ")
+                );
+            }
+        }
+        ReportFormat::Csv => print!("{}", patterns_csv(&sources, &patterns)),
+        ReportFormat::Json => println!("{}", serde_json::to_string_pretty(&patterns)?),
+    }
+    Ok(())
+}
+
+/// Intersection patterns as CSV: a 0 or 1 column for each source, then the
+/// number of domains in exactly those sources.
+pub fn patterns_csv(sources: &[String], patterns: &[IntersectionPattern]) -> String {
+    let mut out: String = sources
+        .iter()
+        .map(|s| format!("{},", csv_field(s)))
+        .collect();
+    out.push_str("domains\n");
+    for p in patterns {
+        for source in sources {
+            out.push_str(if p.sources.contains(source) {
+                "1,"
+            } else {
+                "0,"
+            });
+        }
+        out.push_str(&format!("{}\n", p.domains));
+    }
+    out
+}
+
+/// Whether a list names a tag rather than a shortcut.
+fn is_tag(list: &str) -> bool {
+    !get_shortcuts().contains_key(list) && get_unique_tags().iter().any(|t| t == list)
+}
+
+/// The domains of a list given as a shortcut, a tag, a URL, or a file. A tag
+/// stands for the amalgam of the sources which carry it.
+pub async fn load_domains(
+    args: &Arguments,
+    fetcher: &dyn Fetcher,
+    list: &str,
+) -> Result<Domains, Error> {
+    if is_tag(list) {
+        let amalgam = Amalgam::with_args(
+            get_source_urls_by_tags(&vec![list.to_string()]),
+            args.clone(),
+        )
+        .await?;
+        return Ok(amalgam.domains);
+    }
+    let mut source = Hostssource {
+        args: args.clone(),
+        ..Default::default()
+    };
+    source.load_with(list, Some(fetcher)).await?;
+    Ok(source.domains)
+}
+
+/// Tally the overlap between lists.
+pub fn overlap(lists: &[(String, Domains)]) -> Overlap {
+    let n = lists.len();
+    // how many lists each domain is in
+    let mut membership: HashMap<&str, usize> = HashMap::new();
+    for (_, domains) in lists {
+        for domain in domains {
+            *membership.entry(domain.as_str()).or_insert(0) += 1;
+        }
+    }
+
+    let mut intersections = vec![vec![0; n]; n];
+    let mut jaccard = vec![vec![0.0; n]; n];
+    for i in 0..n {
+        for j in i..n {
+            let (a, b) = (&lists[i].1, &lists[j].1);
+            let (small, large) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+            let common = small.iter().filter(|d| large.contains(*d)).count();
+            let union = a.len() + b.len() - common;
+            let similarity = if union == 0 {
+                0.0
+            } else {
+                common as f64 / union as f64
+            };
+            intersections[i][j] = common;
+            intersections[j][i] = common;
+            jaccard[i][j] = similarity;
+            jaccard[j][i] = similarity;
+        }
+    }
+
+    Overlap {
+        lists: lists.iter().map(|(name, _)| name.to_string()).collect(),
+        domains: lists.iter().map(|(_, d)| d.len()).collect(),
+        unique: lists
+            .iter()
+            .map(|(_, domains)| {
+                domains
+                    .iter()
+                    .filter(|d| membership.get(d.as_str()) == Some(&1))
+                    .count()
+            })
+            .collect(),
+        intersections,
+        jaccard,
+    }
+}
+
+/// The overlap as aligned tables, with lists numbered to keep columns narrow.
+pub fn overlap_table(overlap: &Overlap) -> String {
+    let mut out = String::new();
+    out.push_str(&format!(
+        "{:>3}  {:>10}  {:>10}  List\n",
+        "#", "Domains", "Unique"
+    ));
+    for (i, list) in overlap.lists.iter().enumerate() {
+        out.push_str(&format!(
+            "{:>3}  {:>10}  {:>10}  {}\n",
+            i + 1,
+            overlap.domains[i].to_formatted_string(&Locale::en),
+            overlap.unique[i].to_formatted_string(&Locale::en),
+            list
+        ));
+    }
+
+    let header: String = (1..=overlap.lists.len())
+        .map(|i| format!("{:>10}", i))
+        .collect();
+    out.push_str(&format!("\nIntersections:\n{:>3}{}\n", "", header));
+    for (i, row) in overlap.intersections.iter().enumerate() {
+        let cells: String = row
+            .iter()
+            .map(|c| format!("{:>10}", c.to_formatted_string(&Locale::en)))
+            .collect();
+        out.push_str(&format!("{:>3}{}\n", i + 1, cells));
+    }
+    out.push_str(&format!("\nJaccard similarity:\n{:>3}{}\n", "", header));
+    for (i, row) in overlap.jaccard.iter().enumerate() {
+        let cells: String = row.iter().map(|c| format!("{:>10.3}", c)).collect();
+        out.push_str(&format!("{:>3}{}\n", i + 1, cells));
+    }
+    out
+}
+
+/// The overlap as CSV, one row per pair of lists.
+pub fn overlap_csv(overlap: &Overlap) -> String {
+    let mut out = String::from("list_a,domains_a,unique_a,list_b,intersection,jaccard\n");
+    for (i, a) in overlap.lists.iter().enumerate() {
+        for (j, b) in overlap.lists.iter().enumerate() {
+            out.push_str(&format!(
+                "{},{},{},{},{},{:.6}\n",
+                csv_field(a),
+                overlap.domains[i],
+                overlap.unique[i],
+                csv_field(b),
+                overlap.intersections[i][j],
+                overlap.jaccard[i][j]
+            ));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(name: &str, domains: &[&str]) -> (String, Domains) {
+        (
+            name.to_string(),
+            domains.iter().map(|d| d.to_string()).collect(),
+        )
+    }
+
+    fn sample() -> Overlap {
+        overlap(&[
+            list("a", &["one.com", "two.com", "three.com"]),
+            list("b", &["two.com", "three.com", "four.com"]),
+            list("c", &["five.com"]),
+        ])
+    }
+
+    #[test]
+    fn test_overlap_tallies_intersections_jaccard_and_unique_domains() {
+        let overlap = sample();
+        assert_eq!(overlap.domains, vec![3, 3, 1]);
+        assert_eq!(overlap.unique, vec![1, 1, 1]);
+        assert_eq!(
+            overlap.intersections,
+            vec![vec![3, 2, 0], vec![2, 3, 0], vec![0, 0, 1]]
+        );
+        assert_eq!(overlap.jaccard[0][1], 0.5);
+        assert_eq!(overlap.jaccard[1][0], 0.5);
+        assert_eq!(overlap.jaccard[2][2], 1.0);
+        assert_eq!(overlap.jaccard[0][2], 0.0);
+    }
+
+    #[test]
+    fn test_patterns_csv_flags_the_sources_of_each_pattern() {
+        let sources = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+        let patterns = vec![
+            IntersectionPattern {
+                sources: vec!["a".to_string(), "c".to_string()],
+                domains: 12,
+            },
+            IntersectionPattern {
+                sources: vec!["b".to_string()],
+                domains: 3,
+            },
+        ];
+        assert_eq!(
+            patterns_csv(&sources, &patterns),
+            "a,b,c,domains\n1,0,1,12\n0,1,0,3\n"
+        );
+    }
+
+    #[test]
+    fn test_overlap_csv_has_a_row_per_pair() {
+        let csv = overlap_csv(&sample());
+        assert_eq!(csv.lines().count(), 1 + 9);
+        assert!(csv.contains("\na,3,1,b,2,0.500000\n"), "{}", csv);
+    }
+}
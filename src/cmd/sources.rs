@@ -0,0 +1,308 @@
+//! Source related subcommands.
+//!
+use crate::{
+    Arguments, Commands,
+    cmd::cache,
+    compression::decompress,
+    config::{SourceSpec, get_sources_json},
+    fetch::{Fetcher, fetcher},
+    sniff::sniff_markup,
+    types::Hostssource,
+};
+use anyhow::{Error, bail};
+use clap::Subcommand;
+use num_format::{Locale, ToFormattedString};
+use serde::Serialize;
+use std::fs;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+#[derive(Clone, Debug, Subcommand)]
+/// Enum containing the possible actions for the `sources` subcommand.
+pub enum SourcesCommands {
+    /// Check the health of every source
+    Check {
+        #[clap(long)]
+        /// Check the cached copies instead, where there are any
+        cached: bool,
+    },
+}
+
+/// The health of one source.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct SourceHealth {
+    pub name: String,
+    pub url: String,
+    /// The HTTP status, or `None` when read from the cache or unreachable.
+    pub status: Option<u16>,
+    pub milliseconds: u128,
+    /// The size of the body, in bytes.
+    pub size: usize,
+    pub last_modified: Option<String>,
+    pub domains: usize,
+    /// The share of entries which are invalid.
+    pub invalid_ratio: f64,
+    pub days_since_change: Option<i64>,
+    pub error: Option<String>,
+}
+
+impl SourceHealth {
+    /// Whether the source answered, and with something.
+    pub fn is_healthy(&self) -> bool {
+        self.error.is_none()
+            && self.status.is_none_or(|s| (200..300).contains(&s))
+            && self.domains > 0
+    }
+}
+
+// Sources command implementation
+pub async fn execute(args: Arguments) -> Result<(), Error> {
+    if args.verbose {
+        println!("Handled by 'sources'.");
+    }
+    match &args.command {
+        Some(Commands::Sources {
+            sourcesaction: Some(SourcesCommands::Check { cached }),
+        }) => check(&args, *cached).await,
+        _ => bail!("No such sources subcommand."),
+    }
+}
+
+/// Check every source, and fail when any is unhealthy.
+async fn check(args: &Arguments, cached: bool) -> Result<(), Error> {
+    let specs: Vec<SourceSpec> = serde_json::from_str(&get_sources_json())?;
+    let fetcher = fetcher(args)?;
+    let mut checked: Vec<SourceHealth> = vec![];
+    for spec in &specs {
+        if args.verbose {
+            println!("Checking {}", spec.url);
+        }
+        checked.push(check_source(args, fetcher.as_ref(), spec, cached).await);
+    }
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&checked)?);
+    } else {
+        print!("{}", health_table(&checked));
+    }
+    let unhealthy = checked.iter().filter(|h| !h.is_healthy()).count();
+    if unhealthy > 0 {
+        bail!("{} of {} sources are unhealthy.", unhealthy, checked.len());
+    }
+    Ok(())
+}
+
+/// Fetch a source, or with `cached` read it from the cache where it is
+/// cached, and take its measure.
+async fn check_source(
+    args: &Arguments,
+    fetcher: &dyn Fetcher,
+    spec: &SourceSpec,
+    cached: bool,
+) -> SourceHealth {
+    let mut health = SourceHealth {
+        name: spec.name.clone(),
+        url: spec.url.clone(),
+        ..Default::default()
+    };
+    let start = Instant::now();
+    let cache_file = if cached && args.base_url.is_none() {
+        cache::get(spec.url.to_lowercase()).await
+    } else {
+        None
+    };
+    let mut content_type: Option<String> = None;
+    let body = match cache_file {
+        Some(file) => fs::read(file)
+            .map_err(Error::from)
+            .and_then(|bytes| decompress(bytes, None)),
+        None => fetcher.fetch(&spec.url).and_then(|resp| {
+            health.status = Some(resp.status);
+            health.last_modified = resp.last_modified;
+            content_type = resp.content_type;
+            decompress(resp.body, resp.content_encoding.as_deref())
+        }),
+    };
+    health.milliseconds = start.elapsed().as_millis();
+    let body = match body {
+        Ok(body) => body,
+        Err(e) => {
+            health.error = Some(format!("{:#}", e));
+            return health;
+        }
+    };
+
+    health.size = body.len();
+    if let Some(reason) = sniff_markup(content_type.as_deref(), &body) {
+        health.error = Some(format!("a web page ({}), not a hosts file", reason));
+        return health;
+    }
+    let mut source = Hostssource {
+        args: args.clone(),
+        location: spec.url.clone(),
+        format: spec.format,
+        ..Default::default()
+    };
+    source.parse(&body);
+    health.domains = source.domains.len();
+    let entries = source.domains.len() + source.invalid_entries.len();
+    if entries > 0 {
+        health.invalid_ratio = source.invalid_entries.len() as f64 / entries as f64;
+    }
+    let today = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| (d.as_secs() / 86_400) as i64)
+        .unwrap_or_default();
+    health.days_since_change = health
+        .last_modified
+        .as_deref()
+        .and_then(http_date_days)
+        .map(|day| today - day);
+    health
+}
+
+/// The days since the Unix epoch of an HTTP date, like
+/// `Sun, 06 Nov 1994 08:49:37 GMT`.
+pub fn http_date_days(date: &str) -> Option<i64> {
+    const MONTHS: [&str; 12] = [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+    ];
+    let parts: Vec<&str> = date.split_whitespace().collect();
+    let [_, day, month, year, ..] = parts.as_slice() else {
+        return None;
+    };
+    let day: i64 = day.parse().ok()?;
+    let month = MONTHS.iter().position(|m| month.eq_ignore_ascii_case(m))? as i64 + 1;
+    let year: i64 = year.parse().ok()?;
+    // days from civil, after Howard Hinnant
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    Some(era * 146_097 + doe - 719_468)
+}
+
+/// The health of the sources as an aligned table.
+pub fn health_table(checked: &[SourceHealth]) -> String {
+    let mut out = format!(
+        "{:>6}  {:>8}  {:>12}  {:>10}  {:>8}  {:>6}  Source\n",
+        "Status", "Time", "Size", "Domains", "Invalid", "Days"
+    );
+    for h in checked {
+        let status = match (&h.error, h.status) {
+            (Some(_), _) => "error".to_string(),
+            (None, Some(status)) => status.to_string(),
+            (None, None) => "cache".to_string(),
+        };
+        let days = h
+            .days_since_change
+            .map(|d| d.to_string())
+            .unwrap_or_else(|| "-".to_string());
+        out.push_str(&format!(
+            "{:>6}  {:>6}ms  {:>12}  {:>10}  {:>7.1}%  {:>6}  {}\n",
+            status,
+            h.milliseconds.to_formatted_string(&Locale::en),
+            h.size.to_formatted_string(&Locale::en),
+            h.domains.to_formatted_string(&Locale::en),
+            h.invalid_ratio * 100.0,
+            days,
+            h.name
+        ));
+        if let Some(error) = &h.error {
+            out.push_str(&format!("        {}\n", error));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_http_date_days_counts_from_the_epoch() {
+        assert_eq!(http_date_days("Thu, 01 Jan 1970 00:00:00 GMT"), Some(0));
+        assert_eq!(http_date_days("Sun, 06 Nov 1994 08:49:37 GMT"), Some(9_075));
+        assert_eq!(
+            http_date_days("Tue, 29 Feb 2000 12:00:00 GMT"),
+            Some(11_016)
+        );
+        assert_eq!(http_date_days("yesterday"), None);
+    }
+
+    #[async_std::test]
+    async fn test_check_source_fetches_and_flags_web_pages() {
+        let args = Arguments {
+            base_url: Some(crate::fetch::FIXTURES.to_string()),
+            ..Default::default()
+        };
+        let spec = |url: &str| SourceSpec {
+            name: "test".to_string(),
+            url: url.to_string(),
+            destination: String::new(),
+            tags: vec![],
+            format: Default::default(),
+        };
+        let fetcher = fetcher(&args).unwrap();
+        let healthy = check_source(
+            &args,
+            fetcher.as_ref(),
+            &spec("https://example.com/hosts"),
+            false,
+        )
+        .await;
+        assert!(healthy.is_healthy(), "{:?}", healthy);
+        assert_eq!((healthy.status, healthy.domains), (Some(200), 2));
+        let page = check_source(
+            &args,
+            fetcher.as_ref(),
+            &spec("https://example.com/error-page"),
+            false,
+        )
+        .await;
+        assert!(!page.is_healthy());
+        assert!(
+            page.error
+                .as_deref()
+                .is_some_and(|e| e.contains("web page")),
+            "{:?}",
+            page
+        );
+    }
+
+    #[test]
+    fn test_health_table_flags_errors_and_unhealthy_sources() {
+        let healthy = SourceHealth {
+            name: "adaway".to_string(),
+            status: Some(200),
+            milliseconds: 1_234,
+            size: 100_000,
+            domains: 5_000,
+            invalid_ratio: 0.001,
+            days_since_change: Some(12),
+            ..Default::default()
+        };
+        let dead = SourceHealth {
+            name: "gone".to_string(),
+            status: Some(404),
+            ..Default::default()
+        };
+        let unreachable = SourceHealth {
+            name: "offline".to_string(),
+            error: Some("connection refused".to_string()),
+            ..Default::default()
+        };
+        assert!(healthy.is_healthy());
+        assert!(!dead.is_healthy());
+        assert!(!unreachable.is_healthy());
+        let table = health_table(&[healthy, dead, unreachable]);
+        assert!(
+            table
+                .contains("   200   1,234ms       100,000       5,000      0.1%      12  adaway\n"),
+            "{}",
+            table
+        );
+        assert!(table.contains("   404"), "{}", table);
+        assert!(table.contains(" error") && table.contains("connection refused"));
+    }
+}
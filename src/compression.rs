@@ -0,0 +1,250 @@
+//! Compressed and archived sources.
+//!
+//! Some feeds are published gzipped, xz'd, zstd'd, or zipped. We detect these
+//! from their leading magic bytes, or from the `Content-Encoding` the server
+//! sends, and decompress them before parsing.
+use anyhow::{Context, bail};
+use flate2::{Compression as GzLevel, read::GzDecoder, write::GzEncoder};
+use num_format::{Locale, ToFormattedString};
+use std::io::{self, BufReader, Cursor, Read, Write};
+
+/// The compression of a body.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Xz,
+    Zstd,
+    Zip,
+}
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const XZ_MAGIC: &[u8] = &[0xfd, b'7', b'z', b'X', b'Z', 0x00];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const ZIP_MAGIC: &[u8] = &[b'P', b'K', 0x03, 0x04];
+
+/// Detect the compression of a body from its magic bytes, else from its
+/// `Content-Encoding`, if any.
+pub fn detect(bytes: &[u8], content_encoding: Option<&str>) -> Compression {
+    if bytes.starts_with(GZIP_MAGIC) {
+        Compression::Gzip
+    } else if bytes.starts_with(XZ_MAGIC) {
+        Compression::Xz
+    } else if bytes.starts_with(ZSTD_MAGIC) {
+        Compression::Zstd
+    } else if bytes.starts_with(ZIP_MAGIC) {
+        Compression::Zip
+    } else {
+        match content_encoding
+            .map(|e| e.trim().to_ascii_lowercase())
+            .as_deref()
+        {
+            Some("gzip") | Some("x-gzip") => Compression::Gzip,
+            Some("xz") => Compression::Xz,
+            Some("zstd") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+}
+
+/// The most a body may decompress to, against decompression bombs.
+pub const MAX_DECOMPRESSED: usize = 512 * 1024 * 1024;
+
+/// Decompress a body into text. The files of a zip archive are joined, one
+/// after the other.
+pub fn decompress(bytes: Vec<u8>, content_encoding: Option<&str>) -> anyhow::Result<String> {
+    decompress_limited(bytes, content_encoding, MAX_DECOMPRESSED)
+}
+
+/// Decompress a body into text, failing when it would exceed `limit` bytes.
+fn decompress_limited(
+    bytes: Vec<u8>,
+    content_encoding: Option<&str>,
+    limit: usize,
+) -> anyhow::Result<String> {
+    let compression = detect(&bytes, content_encoding);
+    let decompressed = match compression {
+        Compression::None => bytes,
+        Compression::Gzip => {
+            let mut out = vec![];
+            read_limited(GzDecoder::new(bytes.as_slice()), &mut out, limit)
+                .context("Unable to decompress gzip body.")?;
+            out
+        }
+        Compression::Xz => {
+            let mut out = LimitedWriter {
+                out: vec![],
+                limit,
+                exceeded: false,
+            };
+            let result = lzma_rs::xz_decompress(&mut BufReader::new(bytes.as_slice()), &mut out);
+            if out.exceeded {
+                bail!(too_large(limit));
+            }
+            result.context("Unable to decompress xz body.")?;
+            out.out
+        }
+        Compression::Zstd => {
+            let mut out = vec![];
+            let decoder = ruzstd::decoding::StreamingDecoder::new(bytes.as_slice())
+                .context("Unable to decompress zstd body.")?;
+            read_limited(decoder, &mut out, limit).context("Unable to decompress zstd body.")?;
+            out
+        }
+        Compression::Zip => {
+            let mut archive =
+                zip::ZipArchive::new(Cursor::new(bytes)).context("Unable to open zip archive.")?;
+            let mut out = vec![];
+            for i in 0..archive.len() {
+                let file = archive.by_index(i)?;
+                if file.is_dir() {
+                    continue;
+                }
+                read_limited(file, &mut out, limit).context("Unable to decompress zip archive.")?;
+                if !out.ends_with(b"\n") {
+                    out.push(b'\n');
+                }
+            }
+            out
+        }
+    };
+    Ok(String::from_utf8_lossy(&decompressed).into_owned())
+}
+
+fn too_large(limit: usize) -> String {
+    format!(
+        "The body decompresses to more than {} bytes, the most we accept.",
+        limit.to_formatted_string(&Locale::en)
+    )
+}
+
+/// Read to the end, onto `out`, failing once `out` would exceed `limit`.
+fn read_limited(reader: impl Read, out: &mut Vec<u8>, limit: usize) -> anyhow::Result<()> {
+    let room = limit.saturating_sub(out.len()) as u64;
+    // one byte more than the room tells a body at the limit from one beyond it
+    reader.take(room + 1).read_to_end(out)?;
+    if out.len() > limit {
+        bail!(too_large(limit));
+    }
+    Ok(())
+}
+
+/// A writer which refuses to grow past its limit.
+struct LimitedWriter {
+    out: Vec<u8>,
+    limit: usize,
+    exceeded: bool,
+}
+
+impl Write for LimitedWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.out.len() + buf.len() > self.limit {
+            self.exceeded = true;
+            return Err(io::Error::other(too_large(self.limit)));
+        }
+        self.out.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Gzip text, as the cache stores bodies when `--compress-cache` is given.
+pub fn compress(text: &str) -> anyhow::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(vec![], GzLevel::default());
+    encoder.write_all(text.as_bytes())?;
+    Ok(encoder.finish()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "# test\n0.0.0.0 example.com\n";
+
+    #[test]
+    fn test_plain_text_passes_through() {
+        assert_eq!(detect(TEXT.as_bytes(), None), Compression::None);
+        assert_eq!(decompress(TEXT.as_bytes().to_vec(), None).unwrap(), TEXT);
+    }
+
+    #[test]
+    fn test_gzip_round_trips_and_is_detected() {
+        let gzipped = compress(TEXT).unwrap();
+        assert_eq!(detect(&gzipped, None), Compression::Gzip);
+        assert_eq!(decompress(gzipped, None).unwrap(), TEXT);
+    }
+
+    #[test]
+    fn test_xz_and_zstd_are_detected_and_decompressed() {
+        let mut xz = vec![];
+        lzma_rs::xz_compress(&mut TEXT.as_bytes(), &mut xz).unwrap();
+        assert_eq!(detect(&xz, None), Compression::Xz);
+        assert_eq!(decompress(xz, None).unwrap(), TEXT);
+
+        let zstd = ruzstd::encoding::compress_to_vec(
+            TEXT.as_bytes(),
+            ruzstd::encoding::CompressionLevel::Fastest,
+        );
+        assert_eq!(detect(&zstd, None), Compression::Zstd);
+        assert_eq!(decompress(zstd, None).unwrap(), TEXT);
+    }
+
+    #[test]
+    fn test_zip_archives_join_their_files() {
+        let mut zipped = Cursor::new(vec![]);
+        {
+            let mut writer = zip::ZipWriter::new(&mut zipped);
+            let options = zip::write::SimpleFileOptions::default();
+            writer.start_file("a.txt", options).unwrap();
+            writer.write_all(b"0.0.0.0 a.com").unwrap();
+            writer.start_file("b.txt", options).unwrap();
+            writer.write_all(b"0.0.0.0 b.com\n").unwrap();
+            writer.finish().unwrap();
+        }
+        let zipped = zipped.into_inner();
+        assert_eq!(detect(&zipped, None), Compression::Zip);
+        assert_eq!(
+            decompress(zipped, None).unwrap(),
+            "0.0.0.0 a.com\n0.0.0.0 b.com\n"
+        );
+    }
+
+    #[test]
+    fn test_bodies_decompressing_past_the_limit_are_refused() {
+        let big = "0.0.0.0 example.com\n".repeat(100);
+        let mut xz = vec![];
+        lzma_rs::xz_compress(&mut big.as_bytes(), &mut xz).unwrap();
+        let zstd = ruzstd::encoding::compress_to_vec(
+            big.as_bytes(),
+            ruzstd::encoding::CompressionLevel::Fastest,
+        );
+        let mut zipped = Cursor::new(vec![]);
+        {
+            let mut writer = zip::ZipWriter::new(&mut zipped);
+            writer
+                .start_file("a.txt", zip::write::SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(big.as_bytes()).unwrap();
+            writer.finish().unwrap();
+        }
+        for body in [compress(&big).unwrap(), xz, zstd, zipped.into_inner()] {
+            let error = decompress_limited(body.clone(), None, 1_000).unwrap_err();
+            assert!(
+                format!("{:#}", error).contains("more than 1,000 bytes"),
+                "{:#}",
+                error
+            );
+            assert_eq!(decompress_limited(body, None, big.len() + 1).unwrap(), big);
+        }
+    }
+
+    #[test]
+    fn test_content_encoding_is_used_without_magic_bytes() {
+        assert_eq!(detect(b"", Some("gzip")), Compression::Gzip);
+        assert_eq!(detect(b"", Some(" ZSTD ")), Compression::Zstd);
+        assert_eq!(detect(b"", Some("identity")), Compression::None);
+    }
+}
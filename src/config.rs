@@ -4,6 +4,7 @@ use std::{collections::BTreeMap, fmt, fs, path::PathBuf};
 
 use crate::{
     Arguments,
+    input::InputFormat,
     types::Tags,
     utils::{Combinations, flatten},
 };
@@ -68,6 +69,77 @@ pub fn read_config_file() -> String {
     }
 }
 
+/// HTTP client settings, from the `http` object of rh.json, like
+///
+/// ```json
+/// { "http": { "timeout": 30, "retries": 2, "proxy": "http://proxy:3128",
+///             "ca_bundle": "/etc/ssl/corporate.pem", "timeouts": { "yoyo": 90 } } }
+/// ```
+///
+/// The command line overrides them.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct HttpConfig {
+    /// Seconds to wait for a source.
+    pub timeout: Option<u64>,
+    /// How often to retry a request which fails transiently.
+    pub retries: Option<u32>,
+    pub proxy: Option<String>,
+    pub user_agent: Option<String>,
+    /// A PEM file of root certificates to trust, as well as the usual ones.
+    pub ca_bundle: Option<String>,
+    /// Seconds to wait for particular sources, by name or URL.
+    pub timeouts: BTreeMap<String, u64>,
+}
+
+impl HttpConfig {
+    /// These settings, less any the command line overrides.
+    pub fn overridden_by(self, args: &Arguments) -> HttpConfig {
+        HttpConfig {
+            timeout: args.timeout.or(self.timeout),
+            retries: args.retries.or(self.retries),
+            proxy: args.proxy.clone().or(self.proxy),
+            user_agent: args.user_agent.clone().or(self.user_agent),
+            ca_bundle: args.ca_bundle.clone().or(self.ca_bundle),
+            timeouts: self.timeouts,
+        }
+    }
+
+    /// Seconds to wait for a source, by its URL or name, else in general.
+    pub fn timeout_for(&self, url: &str) -> Option<u64> {
+        self.timeouts
+            .get(url)
+            .or_else(|| get_source_spec_by_url(url).and_then(|spec| self.timeouts.get(&spec.name)))
+            .copied()
+            .or(self.timeout)
+    }
+}
+
+/// Parse the HTTP settings out of the text of rh.json.
+pub fn parse_http_config(json: &str) -> anyhow::Result<HttpConfig> {
+    if json.trim().is_empty() {
+        return Ok(HttpConfig::default());
+    }
+    let config: serde_json::Value = serde_json::from_str(json)?;
+    match config.get("http") {
+        Some(http) => Ok(serde_json::from_value(http.clone())?),
+        None => Ok(HttpConfig::default()),
+    }
+}
+
+/// The HTTP settings of rh.json, overridden by the command line.
+pub fn get_http_config(args: &Arguments) -> HttpConfig {
+    let json = get_config_file()
+        .and_then(|file| Ok(fs::read_to_string(file)?))
+        .unwrap_or_default();
+    parse_http_config(&json)
+        .unwrap_or_else(|e| {
+            eprintln!("Ignoring the http settings in rh.json: {}", e);
+            HttpConfig::default()
+        })
+        .overridden_by(args)
+}
+
 pub fn get_shortcuts() -> BTreeMap<String, String> {
     let mut ret = BTreeMap::new();
     ret.insert(
@@ -464,6 +536,9 @@ pub struct SourceSpec {
     pub url: String,
     pub destination: String,
     pub tags: Tags,
+    /// The format of the source, detected from its content when omitted.
+    #[serde(default)]
+    pub format: InputFormat,
 }
 
 type SourcesSpecs = Vec<SourceSpec>;
@@ -490,6 +565,68 @@ pub fn gettaggroups() -> Vec<Vec<String>> {
     flatten(taggroups)
 }
 
+/// Tag groups for which products are built.
+///
+/// Every group from `gettaggroups` gets the `base` tag, as in the StevenBlack
+/// project where each alternate list extends the base list.
+pub fn get_product_taggroups() -> Vec<Tags> {
+    let mut products: Vec<Tags> = vec![];
+    for mut tags in gettaggroups() {
+        if !tags.contains(&"base".to_string()) {
+            tags.push("base".to_string());
+        }
+        tags.sort();
+        if !products.contains(&tags) {
+            products.push(tags);
+        }
+    }
+    // fewest tags first, then alphabetically
+    products.sort_by(|a, b| a.len().cmp(&b.len()).then(a.cmp(b)));
+    products
+}
+
+/// The product name for a group of tags, built from the initial of each
+/// extension tag, so `["base", "fakenews", "gambling"]` yields `fg`.
+pub fn get_product_name(tags: &Tags) -> String {
+    let name: String = tags
+        .iter()
+        .filter(|t| *t != "base")
+        .filter_map(|t| t.chars().next())
+        .collect();
+    if name.is_empty() {
+        "base".to_string()
+    } else {
+        name
+    }
+}
+
+/// The destination folder of a product, relative to the build root.
+pub fn get_product_destination(tags: &Tags) -> String {
+    let extensions: Vec<&str> = tags
+        .iter()
+        .filter(|t| *t != "base")
+        .map(|t| t.as_str())
+        .collect();
+    if extensions.is_empty() {
+        "./".to_string()
+    } else {
+        format!("./alternates/{}", extensions.join("-"))
+    }
+}
+
+/// The unique source urls for a group of tags.
+pub fn get_source_urls_by_tags(tags: &Tags) -> Vec<String> {
+    let mut urls: Vec<String> = vec![];
+    for tag in tags {
+        for source in get_sources_by_tag(tag.to_string()) {
+            if !urls.contains(&source.url) {
+                urls.push(source.url);
+            }
+        }
+    }
+    urls
+}
+
 #[allow(dead_code)]
 pub fn get_unique_tags() -> Tags {
     // yields all the unique tags we have
@@ -536,6 +673,14 @@ pub fn get_source_names_by_tag(tag: String) -> Vec<String> {
     sources
 }
 
+/// The source specification for a url, if there is one.
+pub fn get_source_spec_by_url(url: &str) -> Option<SourceSpec> {
+    let json = get_sources_json();
+    let config: SourcesSpecs =
+        serde_json::from_str(json.as_str()).expect("Invalid JSON for getting sources.");
+    config.into_iter().find(|x| x.url == url)
+}
+
 #[allow(dead_code)]
 pub fn get_sources_json() -> String {
     r#"[
@@ -702,6 +847,33 @@ mod tests {
         dbg!(cf);
     }
 
+    #[test]
+    fn test_http_config_is_read_from_rh_json_and_overridden_by_the_command_line() {
+        assert_eq!(parse_http_config("").unwrap(), HttpConfig::default());
+        assert_eq!(parse_http_config("{}").unwrap(), HttpConfig::default());
+        let config = parse_http_config(
+            r#"{"http": {"timeout": 20, "proxy": "http://proxy:3128", "timeouts": {"yoyo": 90}}}"#,
+        )
+        .unwrap();
+        assert_eq!(config.timeout, Some(20));
+        assert_eq!(config.proxy, Some("http://proxy:3128".to_string()));
+        assert!(parse_http_config(r#"{"http": {"timeout": "soon"}}"#).is_err());
+
+        let args = Arguments {
+            timeout: Some(5),
+            retries: Some(0),
+            ..Default::default()
+        };
+        let config = config.overridden_by(&args);
+        assert_eq!(config.timeout, Some(5));
+        assert_eq!(config.retries, Some(0));
+        assert_eq!(config.proxy, Some("http://proxy:3128".to_string()));
+        // a source's own timeout, by name, wins
+        let yoyo = get_shortcuts()["yoyo"].clone();
+        assert_eq!(config.timeout_for(&yoyo), Some(90));
+        assert_eq!(config.timeout_for("https://example.com/hosts"), Some(5));
+    }
+
     #[test]
     fn test_shortcuts() {
         let hm = get_shortcuts();
@@ -803,6 +975,48 @@ mod tests {
         assert_eq!(1, 1)
     }
 
+    #[test]
+    fn test_get_product_taggroups_always_include_base() {
+        let products = get_product_taggroups();
+        assert_eq!(products[0], vec!["base".to_string()]);
+        assert!(
+            products
+                .iter()
+                .all(|tags| tags.contains(&"base".to_string())),
+            "Expected every product to include the base tag"
+        );
+        // 4 extension tags yield 2^4 products, including base alone
+        assert_eq!(products.len(), 16);
+    }
+
+    #[test]
+    fn test_get_product_name_and_destination() {
+        let tags: Tags = vec![
+            "base".to_string(),
+            "fakenews".to_string(),
+            "gambling".to_string(),
+        ];
+        assert_eq!(get_product_name(&tags), "fg");
+        assert_eq!(
+            get_product_destination(&tags),
+            "./alternates/fakenews-gambling"
+        );
+        let tags: Tags = vec!["base".to_string()];
+        assert_eq!(get_product_name(&tags), "base");
+        assert_eq!(get_product_destination(&tags), "./");
+    }
+
+    #[test]
+    fn test_get_source_urls_by_tags_has_no_duplicates() {
+        let tags: Tags = vec!["base".to_string(), "porn".to_string()];
+        let urls = get_source_urls_by_tags(&tags);
+        let mut deduped = urls.clone();
+        deduped.sort();
+        deduped.dedup();
+        assert_eq!(urls.len(), deduped.len());
+        assert!(urls.len() > get_sources_by_tag("base".to_string()).len());
+    }
+
     #[test]
     fn test_grouping_config_json_data() {
         // this test tells us if data destination folders exist.
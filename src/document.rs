@@ -0,0 +1,193 @@
+//! Hosts files parsed with their structure intact.
+//!
+//! Normalizing a source keeps only its domains. A `Document` also keeps the
+//! header, the section comments which explain why groups of domains are
+//! blocked, and the inline comments on entries, so a file can be rewritten
+//! without losing its annotations.
+//!
+//! `rh fmt` rewrites a `Document` whole. A single-source `rh build` keeps
+//! its section comments, but not the inline ones, since entries are written
+//! anew. Builds of several sources, `diff`, and the other commands work from
+//! the normalized domains, and their output carries no source comments.
+use crate::types::{Domain, IPaddress};
+use crate::utils::norm_string;
+use std::net::IpAddr;
+
+/// A line which blocks, or names, one or more domains.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Entry {
+    /// The 1-based line number in the source, or 0 for new entries.
+    pub line: usize,
+    /// The leading address, or empty when the line has none.
+    pub ip_address: IPaddress,
+    /// The domains, as written.
+    pub domains: Vec<Domain>,
+    /// The inline comment, without its `#`.
+    pub comment: Option<String>,
+}
+
+impl Entry {
+    fn render(&self) -> String {
+        let mut line = self.domains.join(" ");
+        if !self.ip_address.is_empty() {
+            line = format!("{} {}", self.ip_address, line);
+        }
+        if let Some(comment) = &self.comment {
+            line = format!("{} # {}", line, comment);
+        }
+        line
+    }
+}
+
+/// A run of entries and the comment lines which introduce them.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Section {
+    /// Whether a blank line separates this section from what precedes it.
+    pub blank_before: bool,
+    pub comments: Vec<String>,
+    pub entries: Vec<Entry>,
+}
+
+/// A hosts file: its header, then its sections.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Document {
+    /// The leading comment lines, as in `front_matter`.
+    pub header: Vec<String>,
+    pub sections: Vec<Section>,
+}
+
+impl Document {
+    /// Parse the lines of a hosts file. Whitespace is normalized, and runs of
+    /// blank lines count as one.
+    pub fn parse(lines: &[String]) -> Document {
+        let mut document = Document::default();
+        let mut lines = lines
+            .iter()
+            .enumerate()
+            .map(|(i, l)| (i + 1, norm_string(l)));
+        let mut pending: Option<(usize, String)> = None;
+        for (n, line) in lines.by_ref() {
+            if line.starts_with('#') {
+                document.header.push(line);
+            } else {
+                pending = Some((n, line));
+                break;
+            }
+        }
+
+        let mut section = Section::default();
+        let mut blank = false;
+        for (n, line) in pending.into_iter().chain(lines) {
+            if line.is_empty() {
+                blank = true;
+                continue;
+            }
+            let is_comment = line.starts_with('#');
+            // a blank line, or a comment after entries, starts a new section
+            if blank || (is_comment && !section.entries.is_empty()) {
+                if section != Section::default() {
+                    document.sections.push(section);
+                }
+                section = Section {
+                    blank_before: blank,
+                    ..Default::default()
+                };
+                blank = false;
+            }
+            if is_comment {
+                section.comments.push(line);
+            } else {
+                section.entries.push(parse_entry(n, &line));
+            }
+        }
+        if section != Section::default() {
+            document.sections.push(section);
+        }
+        document
+    }
+
+    /// The lines of the document, with normalized whitespace.
+    pub fn render(&self) -> Vec<String> {
+        let mut lines: Vec<String> = self.header.clone();
+        for section in &self.sections {
+            if section.blank_before && !lines.is_empty() {
+                lines.push(String::new());
+            }
+            lines.extend(section.comments.iter().cloned());
+            lines.extend(section.entries.iter().map(|e| e.render()));
+        }
+        lines
+    }
+}
+
+fn parse_entry(line: usize, text: &str) -> Entry {
+    let (text, comment) = match text.split_once('#') {
+        Some((text, comment)) => (text.trim(), Some(comment.trim().to_string())),
+        None => (text, None),
+    };
+    let mut elements: Vec<&str> = text.split_whitespace().collect();
+    let ip_address = if elements.len() > 1 && elements[0].parse::<IpAddr>().is_ok() {
+        elements.remove(0)
+    } else {
+        ""
+    };
+    Entry {
+        line,
+        ip_address: ip_address.to_string(),
+        domains: elements.iter().map(|e| e.to_string()).collect(),
+        comment: comment.filter(|c| !c.is_empty()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.lines().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn test_document_attaches_comments_to_sections_and_entries() {
+        let document = Document::parse(&lines(
+            "# Title: test\n# Updated: today\n\n# Ads\n0.0.0.0 ads.example.com # seen in the wild\n0.0.0.0 a.com b.com\n# Trackers\n0.0.0.0 t.example.org",
+        ));
+        assert_eq!(document.header.len(), 2);
+        assert_eq!(
+            document.sections.len(),
+            2,
+            "Expected 2 sections, but got: {:?}",
+            document.sections
+        );
+        assert_eq!(document.sections[0].comments, vec!["# Ads".to_string()]);
+        assert!(document.sections[0].blank_before);
+        assert_eq!(
+            document.sections[0].entries[0],
+            Entry {
+                line: 5,
+                ip_address: "0.0.0.0".to_string(),
+                domains: vec!["ads.example.com".to_string()],
+                comment: Some("seen in the wild".to_string()),
+            }
+        );
+        assert_eq!(document.sections[0].entries[1].domains.len(), 2);
+        assert_eq!(
+            document.sections[1].comments,
+            vec!["# Trackers".to_string()]
+        );
+        assert!(!document.sections[1].blank_before);
+    }
+
+    #[test]
+    fn test_document_round_trips_a_normalized_file() {
+        let text = lines(
+            "# Title: test\n\n127.0.0.1 localhost\n\n# Ads\n# more about ads\n0.0.0.0 ads.example.com # why\nexample.net\n\n0.0.0.0 x.example.org",
+        );
+        assert_eq!(Document::parse(&text).render(), text);
+        // whitespace is normalized, and blank lines collapsed
+        assert_eq!(
+            Document::parse(&lines("#  a\n\n\n0.0.0.0\t b.com   #  c ")).render(),
+            lines("# a\n\n0.0.0.0 b.com # c")
+        );
+    }
+}
@@ -0,0 +1,328 @@
+//! Fetching sources.
+//!
+//! Sources are fetched through a `Fetcher`, so they can come from the web,
+//! from a local mirror, or from test fixtures. With `--base-url`, a source's
+//! URL is rebased onto another: `https://host/path` is fetched from
+//! `<base>/host/path`, whether the base is a folder or a local server.
+//!
+//! Over HTTP, timeouts, retries, the proxy, the user agent, and extra root
+//! certificates come from the `http` settings of rh.json, or the command line.
+use crate::Arguments;
+use crate::config::{HttpConfig, get_http_config};
+use anyhow::{Context, anyhow, bail};
+use std::fs;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+/// Seconds to wait for a source, unless configured otherwise.
+pub const DEFAULT_TIMEOUT: u64 = 30;
+/// Retries of a request which fails transiently, unless configured otherwise.
+pub const DEFAULT_RETRIES: u32 = 2;
+/// The wait before the first retry, doubled for each one after.
+pub const BACKOFF: Duration = Duration::from_millis(500);
+/// Who we say we are, unless configured otherwise.
+pub const USER_AGENT: &str = concat!(
+    "rh/",
+    env!("CARGO_PKG_VERSION"),
+    " (+",
+    env!("CARGO_PKG_HOMEPAGE"),
+    ")"
+);
+
+/// A fetched body, and what the server said about it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Response {
+    pub status: u16,
+    pub body: Vec<u8>,
+    pub content_type: Option<String>,
+    pub content_encoding: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+impl Response {
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+/// Something which can fetch a URL.
+pub trait Fetcher {
+    fn fetch(&self, url: &str) -> anyhow::Result<Response>;
+}
+
+/// Fetches over HTTP(S), from the URL itself or from under a base URL.
+#[derive(Clone, Debug)]
+pub struct HttpFetcher {
+    pub base_url: Option<String>,
+    pub config: HttpConfig,
+    client: reqwest::blocking::Client,
+}
+
+impl HttpFetcher {
+    /// A fetcher with its client built, once, from the configuration.
+    pub fn new(base_url: Option<String>, config: HttpConfig) -> anyhow::Result<HttpFetcher> {
+        let client = HttpFetcher::client(&config)?;
+        Ok(HttpFetcher {
+            base_url,
+            config,
+            client,
+        })
+    }
+
+    fn client(config: &HttpConfig) -> anyhow::Result<reqwest::blocking::Client> {
+        let mut builder = reqwest::blocking::Client::builder().user_agent(
+            config
+                .user_agent
+                .clone()
+                .unwrap_or_else(|| USER_AGENT.to_string()),
+        );
+        if let Some(proxy) = &config.proxy {
+            builder = builder.proxy(
+                reqwest::Proxy::all(proxy).with_context(|| format!("Invalid proxy {}.", proxy))?,
+            );
+        }
+        if let Some(ca_bundle) = &config.ca_bundle {
+            let pem = fs::read(ca_bundle)
+                .with_context(|| format!("Unable to read CA bundle {}.", ca_bundle))?;
+            let certificates = reqwest::Certificate::from_pem_bundle(&pem)
+                .with_context(|| format!("Invalid CA bundle {}.", ca_bundle))?;
+            if certificates.is_empty() {
+                bail!("The CA bundle {} has no certificates.", ca_bundle);
+            }
+            builder = builder.tls_certs_merge(certificates);
+        }
+        Ok(builder.build()?)
+    }
+}
+
+impl Fetcher for HttpFetcher {
+    fn fetch(&self, url: &str) -> anyhow::Result<Response> {
+        let timeout = Duration::from_secs(self.config.timeout_for(url).unwrap_or(DEFAULT_TIMEOUT));
+        let url = match &self.base_url {
+            Some(base) => rebase(url, base),
+            None => url.to_string(),
+        };
+        let retries = self.config.retries.unwrap_or(DEFAULT_RETRIES);
+        with_retries(retries, BACKOFF, || {
+            let resp = match self.client.get(&url).timeout(timeout).send() {
+                Ok(resp) => resp,
+                Err(e) if is_transient_error(&e) => {
+                    return Attempt::Transient(Err(
+                        anyhow!(e).context(format!("Unable to fetch {}.", url))
+                    ));
+                }
+                Err(e) => {
+                    return Attempt::Final(Err(
+                        anyhow!(e).context(format!("Unable to fetch {}.", url))
+                    ));
+                }
+            };
+            let status = resp.status().as_u16();
+            let header = |name| {
+                resp.headers()
+                    .get(name)
+                    .and_then(|v| v.to_str().ok())
+                    .map(|v| v.to_string())
+            };
+            let response = Response {
+                status,
+                content_type: header(reqwest::header::CONTENT_TYPE),
+                content_encoding: header(reqwest::header::CONTENT_ENCODING),
+                last_modified: header(reqwest::header::LAST_MODIFIED),
+                ..Default::default()
+            };
+            match resp.bytes() {
+                Ok(body) if is_transient(status) => Attempt::Transient(Ok(Response {
+                    body: body.to_vec(),
+                    ..response
+                })),
+                Ok(body) => Attempt::Final(Ok(Response {
+                    body: body.to_vec(),
+                    ..response
+                })),
+                Err(e) if is_transient_error(&e) => Attempt::Transient(Err(
+                    anyhow!(e).context(format!("Unable to read the body of {}.", url))
+                )),
+                Err(e) => Attempt::Final(Err(
+                    anyhow!(e).context(format!("Unable to read the body of {}.", url))
+                )),
+            }
+        })
+    }
+}
+
+/// What came of one attempt at a request: something final, or something
+/// which may be better next time.
+pub enum Attempt<T> {
+    Final(T),
+    Transient(T),
+}
+
+/// The longest wait between attempts, as a multiple of the first one.
+const MAX_BACKOFF_FACTOR: u32 = 64;
+
+/// Make an attempt, retrying up to `retries` times while it fails
+/// transiently, waiting `backoff` before the first retry and twice as long
+/// before each one after, up to `MAX_BACKOFF_FACTOR` times `backoff`. The
+/// last attempt's result stands.
+pub fn with_retries<T>(
+    retries: u32,
+    backoff: Duration,
+    mut attempt: impl FnMut() -> Attempt<T>,
+) -> T {
+    let mut retried = 0;
+    loop {
+        match attempt() {
+            Attempt::Final(result) => return result,
+            Attempt::Transient(result) if retried >= retries => return result,
+            Attempt::Transient(_) => {
+                thread::sleep(backoff * 2u32.saturating_pow(retried).min(MAX_BACKOFF_FACTOR));
+                retried += 1;
+            }
+        }
+    }
+}
+
+/// Whether a request error may clear up on its own: a timeout, or a failure
+/// to connect. Anything else, like a bad URL or a redirect loop, won't.
+fn is_transient_error(e: &reqwest::Error) -> bool {
+    e.is_timeout() || e.is_connect()
+}
+
+/// Whether an HTTP status may clear up on its own.
+pub fn is_transient(status: u16) -> bool {
+    matches!(status, 408 | 429 | 500 | 502 | 503 | 504)
+}
+
+/// Serves URLs from a folder, `https://host/path` from `<root>/host/path`.
+#[derive(Clone, Debug, Default)]
+pub struct DirFetcher {
+    pub root: PathBuf,
+}
+
+impl Fetcher for DirFetcher {
+    fn fetch(&self, url: &str) -> anyhow::Result<Response> {
+        let path = rebase(url, &self.root.to_string_lossy());
+        let body = fs::read(&path).with_context(|| format!("No file for {} at {}.", url, path))?;
+        Ok(Response {
+            status: 200,
+            body,
+            ..Default::default()
+        })
+    }
+}
+
+/// The URL, less its scheme, under `base`.
+pub fn rebase(url: &str, base: &str) -> String {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    format!("{}/{}", base.trim_end_matches('/'), rest)
+}
+
+/// The fetcher for the arguments: a folder or server given by `--base-url`,
+/// else the web. Build it once, and fetch every source with it; a bad proxy
+/// or CA bundle fails here, before anything is fetched.
+pub fn fetcher(args: &Arguments) -> anyhow::Result<Box<dyn Fetcher>> {
+    Ok(match &args.base_url {
+        Some(base) if base.starts_with("http://") || base.starts_with("https://") => {
+            Box::new(HttpFetcher::new(Some(base.clone()), get_http_config(args))?)
+        }
+        Some(base) => Box::new(DirFetcher {
+            root: PathBuf::from(base.strip_prefix("file://").unwrap_or(base)),
+        }),
+        None => Box::new(HttpFetcher::new(None, get_http_config(args))?),
+    })
+}
+
+/// Sources for tests, served with `--base-url`.
+#[cfg(test)]
+pub const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rebase_puts_the_host_and_path_under_the_base() {
+        assert_eq!(
+            rebase("https://example.com/a/hosts", "http://localhost:8080/"),
+            "http://localhost:8080/example.com/a/hosts"
+        );
+        assert_eq!(
+            rebase("http://example.com/hosts", "/srv/mirror"),
+            "/srv/mirror/example.com/hosts"
+        );
+    }
+
+    #[test]
+    fn test_with_retries_retries_transient_failures_only() {
+        let mut attempts = 0;
+        let result = with_retries(2, Duration::ZERO, || {
+            attempts += 1;
+            Attempt::Transient(attempts)
+        });
+        assert_eq!((result, attempts), (3, 3));
+
+        let mut attempts = 0;
+        let result = with_retries(5, Duration::ZERO, || {
+            attempts += 1;
+            if attempts < 2 {
+                Attempt::Transient("timed out")
+            } else {
+                Attempt::Final("ok")
+            }
+        });
+        assert_eq!((result, attempts), ("ok", 2));
+
+        // many retries do not overflow the backoff
+        let mut attempts = 0;
+        let result = with_retries(40, Duration::ZERO, || {
+            attempts += 1;
+            Attempt::Transient(attempts)
+        });
+        assert_eq!(result, 41);
+        assert!(is_transient(503) && is_transient(429));
+        assert!(!is_transient(404) && !is_transient(200));
+    }
+
+    #[test]
+    fn test_http_fetcher_reports_a_bad_ca_bundle_or_proxy_before_fetching() {
+        let config = HttpConfig {
+            ca_bundle: Some(format!("{}/example.com/hosts", FIXTURES)),
+            ..Default::default()
+        };
+        let error = HttpFetcher::new(None, config).unwrap_err();
+        assert!(
+            error.to_string().contains("has no certificates"),
+            "{}",
+            error
+        );
+        let config = HttpConfig {
+            proxy: Some("http://[not a proxy".to_string()),
+            ..Default::default()
+        };
+        let error = HttpFetcher::new(None, config).unwrap_err();
+        assert!(error.to_string().contains("Invalid proxy"), "{}", error);
+    }
+
+    #[test]
+    fn test_dir_fetcher_serves_fixtures() {
+        let args = Arguments {
+            base_url: Some(format!("file://{}", FIXTURES)),
+            ..Default::default()
+        };
+        let response = fetcher(&args)
+            .unwrap()
+            .fetch(
+                "https://raw.githubusercontent.com/StevenBlack/hosts/f5d5efab/data/URLHaus/hosts",
+            )
+            .unwrap();
+        assert!(response.is_success());
+        assert!(String::from_utf8_lossy(&response.body).starts_with("# Title: URLHaus"));
+
+        let missing = fetcher(&args)
+            .unwrap()
+            .fetch("https://example.com/nothing/here");
+        assert!(missing.is_err());
+    }
+}
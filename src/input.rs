@@ -0,0 +1,294 @@
+//! Input formats for sources.
+//!
+//! Sources which are not hosts files are translated, line by line, into hosts
+//! syntax so they go through the same normalization as everything else.
+use crate::utils::is_domain;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+
+/// The formats in which sources can be read.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InputFormat {
+    /// Detect the format from the content.
+    #[default]
+    Auto,
+    /// `0.0.0.0 example.com`
+    Hosts,
+    /// `example.com`, one per line
+    Domains,
+    /// AdBlock and uBlock filters, `||example.com^`
+    Adblock,
+    /// `address=/example.com/0.0.0.0`
+    Dnsmasq,
+    /// BIND response policy zone, `example.com CNAME .`
+    Rpz,
+    /// Comma-separated values, such as threat-intel feeds
+    Csv,
+}
+
+/// How many lines we look at when detecting the format of a source.
+const DETECTION_SAMPLE: usize = 200;
+
+/// Detect the format of a source from a sample of its lines.
+pub fn detect_format(lines: &[String]) -> InputFormat {
+    let mut votes: Vec<(InputFormat, usize)> = vec![];
+    for line in lines
+        .iter()
+        .filter_map(|l| classify_line(l))
+        .take(DETECTION_SAMPLE)
+    {
+        match votes.iter_mut().find(|(f, _)| *f == line) {
+            Some((_, n)) => *n += 1,
+            None => votes.push((line, 1)),
+        }
+    }
+    votes
+        .into_iter()
+        .max_by_key(|(_, n)| *n)
+        .map(|(f, _)| f)
+        .unwrap_or(InputFormat::Hosts)
+}
+
+/// The format a single line suggests, if any.
+fn classify_line(line: &str) -> Option<InputFormat> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+        return None;
+    }
+    if line.starts_with('!') || line.starts_with('[') {
+        return Some(InputFormat::Adblock);
+    }
+    if line.starts_with("||") || line.starts_with("@@") || line.contains("##") {
+        return Some(InputFormat::Adblock);
+    }
+    if ["address=/", "server=/", "local=/"]
+        .iter()
+        .any(|p| line.starts_with(p))
+    {
+        return Some(InputFormat::Dnsmasq);
+    }
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    if line.starts_with("$TTL")
+        || tokens
+            .iter()
+            .any(|t| t.eq_ignore_ascii_case("CNAME") || t.eq_ignore_ascii_case("SOA"))
+    {
+        return Some(InputFormat::Rpz);
+    }
+    if tokens.len() > 1 && tokens[0].parse::<IpAddr>().is_ok() {
+        return Some(InputFormat::Hosts);
+    }
+    if line.contains(',') {
+        return Some(InputFormat::Csv);
+    }
+    if tokens.len() == 1 {
+        return Some(InputFormat::Domains);
+    }
+    None
+}
+
+/// Translate a line of the given format into hosts syntax.
+///
+/// Comment lines become `#` comments, and lines that do not block a domain,
+/// like exceptions or zone records, become blank lines.
+pub fn to_hosts_line(format: InputFormat, line: &str) -> String {
+    let line = line.trim();
+    match format {
+        InputFormat::Auto | InputFormat::Hosts | InputFormat::Domains => line.to_string(),
+        InputFormat::Adblock => adblock_line(line),
+        InputFormat::Dnsmasq => dnsmasq_line(line),
+        InputFormat::Rpz => rpz_line(line),
+        InputFormat::Csv => csv_line(line),
+    }
+}
+
+fn adblock_line(line: &str) -> String {
+    if let Some(comment) = line.strip_prefix('!') {
+        return format!("#{}", comment);
+    }
+    if line.starts_with('[') {
+        return format!("# {}", line);
+    }
+    // only basic blocking rules, like `||example.com^` or `||example.com^$important`
+    if let Some(rule) = line.strip_prefix("||")
+        && let Some((domain, rest)) = rule.split_once('^')
+        && (rest.is_empty() || rest.strip_prefix('$').is_some_and(blocks_whole_domain))
+        && !domain.contains(['/', '*'])
+    {
+        return domain.to_string();
+    }
+    String::new()
+}
+
+/// Whether a rule's options leave it blocking the whole domain. Options like
+/// `$third-party` or `$domain=` narrow a rule, and `$badfilter` undoes one.
+fn blocks_whole_domain(options: &str) -> bool {
+    options
+        .split(',')
+        .all(|o| matches!(o.trim(), "important" | "all" | "document" | "doc"))
+}
+
+fn dnsmasq_line(line: &str) -> String {
+    if line.starts_with('#') {
+        return line.to_string();
+    }
+    // address=/a.com/b.com/0.0.0.0, server=/a.com/, local=/a.com/
+    if let Some((option, spec)) = line.split_once("=/") {
+        let parts: Vec<&str> = spec.split('/').collect();
+        if let Some((target, domains)) = parts.split_last()
+            && !domains.is_empty()
+        {
+            let domains = domains.join(" ");
+            let target = target.trim();
+            return match option.trim() {
+                // an empty address, or `#`, answers NXDOMAIN
+                "address" if target.is_empty() || target == "#" => domains,
+                "address" if target.parse::<IpAddr>().is_ok() => {
+                    format!("{} {}", target, domains)
+                }
+                // without an upstream, the domains are answered locally, so not at all
+                "server" | "local" if target.is_empty() => domains,
+                // anything else, like `server=/a.com/1.1.1.1`, forwards the domains
+                _ => String::new(),
+            };
+        }
+    }
+    String::new()
+}
+
+fn rpz_line(line: &str) -> String {
+    if let Some(comment) = line.strip_prefix(';') {
+        return format!("#{}", comment);
+    }
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    // `example.com [ttl] [IN] CNAME .` is a block, `CNAME rpz-passthru.` is not
+    if let Some(i) = tokens.iter().position(|t| t.eq_ignore_ascii_case("CNAME"))
+        && i > 0
+        && matches!(tokens.get(i + 1), Some(&".") | Some(&"*."))
+        && !tokens[0].starts_with('*')
+        && !tokens[0].starts_with('@')
+    {
+        return tokens[0].trim_end_matches('.').to_string();
+    }
+    String::new()
+}
+
+fn csv_line(line: &str) -> String {
+    if line.starts_with('#') {
+        return line.to_string();
+    }
+    for field in line.split(',') {
+        let field = field.trim().trim_matches('"').trim();
+        // take the host from urls, like `http://example.com:8080/path`
+        let host = match field.split_once("://") {
+            Some((_, rest)) => rest
+                .split(['/', ':', '?'])
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+            None => field.to_string(),
+        };
+        if is_domain(&host) {
+            return host;
+        }
+    }
+    String::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.lines().map(|l| l.trim().to_string()).collect()
+    }
+
+    #[test]
+    fn test_detect_format_of_common_lists() {
+        assert_eq!(
+            detect_format(&lines("# hosts\n0.0.0.0 example.com\n0.0.0.0 example.org")),
+            InputFormat::Hosts
+        );
+        assert_eq!(
+            detect_format(&lines("[Adblock Plus 2.0]\n! comment\n||example.com^")),
+            InputFormat::Adblock
+        );
+        assert_eq!(
+            detect_format(&lines(
+                "address=/example.com/0.0.0.0\naddress=/example.org/"
+            )),
+            InputFormat::Dnsmasq
+        );
+        assert_eq!(
+            detect_format(&lines(
+                "$TTL 2h\nexample.com CNAME .\n*.example.com CNAME ."
+            )),
+            InputFormat::Rpz
+        );
+        assert_eq!(
+            detect_format(&lines("example.com\nexample.org")),
+            InputFormat::Domains
+        );
+        assert_eq!(
+            detect_format(&lines("# id,url\n1,\"http://example.com/x\",malware")),
+            InputFormat::Csv
+        );
+        assert_eq!(detect_format(&[]), InputFormat::Hosts);
+    }
+
+    #[test]
+    fn test_adblock_lines_translate_to_hosts_syntax() {
+        let f = InputFormat::Adblock;
+        assert_eq!(to_hosts_line(f, "||example.com^"), "example.com");
+        assert_eq!(to_hosts_line(f, "||example.com^$important"), "example.com");
+        assert_eq!(to_hosts_line(f, "! Title: test"), "# Title: test");
+        assert_eq!(to_hosts_line(f, "@@||example.com^"), "");
+        assert_eq!(to_hosts_line(f, "example.com##.ad"), "");
+        assert_eq!(to_hosts_line(f, "||example.com/ads/*"), "");
+        assert_eq!(
+            to_hosts_line(f, "||example.com^$all,important"),
+            "example.com"
+        );
+        assert_eq!(to_hosts_line(f, "||example.com^$third-party"), "");
+        assert_eq!(to_hosts_line(f, "||example.com^$domain=example.org"), "");
+        assert_eq!(to_hosts_line(f, "||example.com^$removeparam=utm"), "");
+        assert_eq!(to_hosts_line(f, "||example.com^$badfilter"), "");
+    }
+
+    #[test]
+    fn test_dnsmasq_rpz_and_csv_lines_translate_to_hosts_syntax() {
+        let f = InputFormat::Dnsmasq;
+        assert_eq!(
+            to_hosts_line(f, "address=/a.com/b.com/0.0.0.0"),
+            "0.0.0.0 a.com b.com"
+        );
+        assert_eq!(
+            to_hosts_line(f, "address=/example.com/1.2.3.4"),
+            "1.2.3.4 example.com"
+        );
+        assert_eq!(to_hosts_line(f, "address=/example.com/"), "example.com");
+        assert_eq!(to_hosts_line(f, "address=/example.com/#"), "example.com");
+        assert_eq!(to_hosts_line(f, "server=/example.com/"), "example.com");
+        assert_eq!(to_hosts_line(f, "local=/example.com/"), "example.com");
+        assert_eq!(to_hosts_line(f, "server=/example.com/1.1.1.1"), "");
+        assert_eq!(
+            to_hosts_line(InputFormat::Rpz, "example.com. 300 IN CNAME ."),
+            "example.com"
+        );
+        assert_eq!(to_hosts_line(InputFormat::Rpz, "*.example.com CNAME ."), "");
+        assert_eq!(
+            to_hosts_line(InputFormat::Rpz, "example.com CNAME rpz-passthru."),
+            ""
+        );
+        assert_eq!(to_hosts_line(InputFormat::Rpz, "; comment"), "# comment");
+        assert_eq!(
+            to_hosts_line(
+                InputFormat::Csv,
+                "\"1\",\"2024-01-01\",\"http://www.example.com:8080/x.exe\",\"online\""
+            ),
+            "www.example.com"
+        );
+        assert_eq!(to_hosts_line(InputFormat::Csv, "id,dateadded,url"), "");
+    }
+}
@@ -6,6 +6,8 @@
 //!
 //! - **cmd**: Handles specific commands and their implementations.
 //! - **config**: Contains configuration management utilities and helpers.
+//! - **input**: Reads sources which are not in hosts file format.
+//! - **output**: Writes hosts lists in various output formats.
 //! - **types**: Defines data structures and types.
 //! - **utils**: Includes utility functions for common tasks.
 //!
@@ -26,26 +28,43 @@
 //!   Defaults to `"base"`.
 //! - **comparehosts** (`Option<String>`): Specifies the hosts file to compare against the main hosts.
 //! - **isolate** (`Option<String>`): Specifies the hosts list to isolate and compare to the main hosts.
-//! - **iplocalhost** (`String`): Defines the IP address to use when listing hosts. Defaults to `"0.0.0.0"`.
+//! - **iplocalhost** (`String`): Defines the IPv4 or IPv6 address to use when listing hosts. Defaults to `"0.0.0.0"`.
+//! - **ipv6** (`Option<Ipv6Mode>`): Emits `::` entries alongside (`paired`) or instead of (`only`) the others.
 //! - **adddefaults** (`bool`): Adds default hosts to the top of the host lists.
 //! - **domains_sort** (`bool`): Enables domain sorting based on hierarchy.
 //! - **output** (`Option<String>`): Specifies an output file; otherwise, stdout is used.
 //! - **plain_output** (`bool`): Generates plain domain listings (domains only, without IP addresses).
+//! - **domains_per_line** (`Option<u8>`): Packs up to 9 domains on each line of hosts output.
+//! - **prune** (`bool`): Removes subdomains of blocked domains for formats that block subdomains.
+//! - **unicode** (`bool`): Outputs internationalized domains in Unicode rather than punycode.
+//! - **format** (`OutputFormat`): The output format for hosts lists. Defaults to `hosts`.
 //! - **quiet** (`bool`): Enables quiet mode, displaying only the number of domains.
 //! - **stats** (`Option<bool>`): Displays statistics about the domains.
 //! - **intersection_list** (`bool`): Outputs the intersection of `mainhosts` and `comparehosts`.
 //! - **rootdomains** (`bool`): Outputs a count of root domains.
 //! - **subdomains** (`bool`): Outputs a count of subdomains.
 //! - **tld** (`bool`): Outputs a tally of top-level domains (TLDs).
+//! - **naive** (`bool`): Tallies root domains and TLDs by label, ignoring the Public Suffix List.
 //! - **limit** (`usize`): Limits the number of TLD/root domain listings. Defaults to 30; `0` for unlimited.
 //! - **skipheaders** (`bool`): Omits file comment headers in the output.
 //! - **showduplicates** (`bool`): Lists duplicate domains when reporting a hosts list.
-//! - **showinvalids** (`bool`): Lists invalid domains when reporting a hosts list.
+//! - **showinvalids** (`bool`): Lists invalid entries, with their line and reason, when reporting a hosts list.
+//! - **showrewrites** (`bool`): Lists entries rewritten to their canonical form.
+//! - **showredirects** (`bool`): Lists entries that redirect to non-null addresses.
+//! - **json** (`bool`): Reports on a hosts list in JSON.
+//! - **redirect_policy** (`RedirectPolicy`): Rewrites or rejects redirecting entries when building.
 //! - **sysclipboard** (`bool`): Uses system clipboard contents as the compare hosts.
 //! - **uniquelist** (`bool`): Outputs unique domain names.
 //! - **verbose** (`bool`): Enables verbose output for development or debugging.
 //! - **command** (`Option<Commands>`): Specifies a subcommand to execute.
 //! - **skipcache** (`bool`): Prevents using cached data.
+//! - **compresscache** (`bool`): Stores cached lists gzipped.
+//! - **base_url** (`Option<String>`): Fetches sources from a folder or server instead, as `<base>/<host>/<path>`.
+//! - **timeout** (`Option<u64>`): Seconds to wait for each source.
+//! - **retries** (`Option<u32>`): How often to retry a request which fails transiently.
+//! - **proxy** (`Option<String>`): The proxy through which to fetch sources.
+//! - **user_agent** (`Option<String>`): The user agent with which to fetch sources.
+//! - **ca_bundle** (`Option<String>`): A PEM file of root certificates to trust, as well as the usual ones.
 //!
 //! ### Methods
 //!
@@ -60,9 +79,24 @@
 //!
 //! - **Build**: Builds hosts files with an optional formula.
 //!   - **formula** (`Option<String>`): Specifies the formula to use.
+//!   - **all_combinations** (`bool`): Builds a product for every combination of tags, and writes their summary to `rh-stats.json` and `rh-stats.md`.
+//!   - **root** (`String`): The folder into which products are built, and their summary written. Defaults to `"."`.
+//!   - **max_change** (`Option<f64>`): The percentage by which a source or product may change in size since the previous build.
+//!   - **max_change_domains** (`Option<usize>`): The number of domains by which a source or product may change since the previous build.
+//!   - **warn_only** (`bool`): Warns, rather than fails, when a change exceeds these limits. These three apply to `--all-combinations` only.
 //! - **Cache**: Manages application cache with subcommands for actions.
 //!   - **cacheaction** (`Option<cmd::cache::CacheCommands>`): A subcommand for cache-specific actions.
+//! - **Fmt**: Rewrites a hosts file canonically, in place, keeping its comments.
+//!   - **file** (`String`): The hosts file to rewrite.
 //! - **Init**: Initializes cache and templates.
+//! - **Lint**: Lints a hosts file, exiting with an error when it has problems.
+//!   - **file** (`String`): The hosts file to lint.
+//! - **Overlap**: Reports the overlap between two or more lists.
+//!   - **lists** (`Vec<String>`): The shortcuts, tags, URLs, or files to compare.
+//!   - **report** (`ReportFormat`): A table, CSV, or JSON. Defaults to `table`.
+//!   - **upset** (`bool`): Counts the domains in every exact combination of sources instead.
+//! - **Sources**: Manages the sources with subcommands for actions.
+//!   - **sourcesaction** (`Option<cmd::sources::SourcesCommands>`): A subcommand for source-specific actions.
 //! - **Info**: Displays additional application information.
 //!
 //! # Functions
@@ -116,9 +150,18 @@ extern crate clap;
 use anyhow::Error;
 use clap::{Parser, Subcommand};
 use config::get_shortcuts;
+use output::{Ipv6Mode, OutputFormat, ReportFormat};
+use types::RedirectPolicy;
+use utils::parse_ip_address;
 
 mod cmd;
+mod compression;
 mod config;
+mod document;
+mod fetch;
+mod input;
+mod output;
+mod sniff;
 mod types;
 mod utils;
 
@@ -130,11 +173,12 @@ mod utils;
 ///
 /// - `mainhosts`:
 ///   The main hosts file acting as the basis for comparison. Defaults to "base".
+///   Use `-` to read it from stdin.
 ///   - Short Flag: `-m`
 ///   - Long Flag: `--main`
 ///
 /// - `comparehosts`:
-///   The hosts file to compare against the `mainhosts`.
+///   The hosts file to compare against the `mainhosts`. Use `-` to read it from stdin.
 ///   - Short Flag: `-c`
 ///   - Long Flag: `--compare`
 ///
@@ -145,9 +189,14 @@ mod utils;
 ///   - Long Flag: `--isolate`
 ///
 /// - `iplocalhost`:
-///   The IP address to associate with listed hosts. Defaults to `0.0.0.0`.
+///   The IPv4 or IPv6 address to associate with listed hosts. Defaults to `0.0.0.0`.
 ///   - Long Flag: `--ip`
 ///
+/// - `ipv6`:
+///   Emit IPv6 null-route entries, `:: domain`, alongside (`paired`) or instead of
+///   (`only`) the entries for `iplocalhost`.
+///   - Long Flag: `--ipv6`
+///
 /// - `adddefaults`:
 ///   Flag to include default hosts at the top of the hosts lists, if enabled.
 ///   - Short Flag: `-d`
@@ -168,6 +217,27 @@ mod utils;
 ///   - Short Flag: `-p`
 ///   - Long Flag: `--plain`
 ///
+/// - `domains_per_line`:
+///   Packs this many domains, from 1 to 9, on each line of hosts output. Windows
+///   resolves large hosts files faster this way, and caps lines at 9 domains.
+///   - Long Flag: `--per-line`
+///
+/// - `prune`:
+///   Removes domains already covered by a blocked parent domain, for output formats
+///   which block subdomains (dnsmasq, unbound, rpz, adblock). For other formats, and
+///   when reporting, only the potential savings are reported.
+///   - Long Flag: `--prune`
+///
+/// - `unicode`:
+///   Domains are kept in canonical ASCII form, with internationalized labels in
+///   punycode. This flag outputs them in Unicode form instead.
+///   - Long Flag: `--unicode`
+///
+/// - `format`:
+///   The output format for hosts lists: hosts, plain, dnsmasq, unbound, rpz, adblock,
+///   or pihole. Defaults to `hosts`. The `--plain` flag overrides this.
+///   - Long Flag: `--format`
+///
 /// - `quiet`:
 ///   Enables quiet output mode, only showing the count of domains.
 ///   - Short Flag: `-q`
@@ -197,6 +267,11 @@ mod utils;
 ///   - Short Flag: `-t`
 ///   - Long Flag: `--tld`
 ///
+/// - `naive`:
+///   Tallies root domains and TLDs naively, from the last two labels and the last label,
+///   rather than with the Public Suffix List. Useful for comparison.
+///   - Long Flag: `--naive`
+///
 /// - `limit`:
 ///   Sets a limit for listing TLDs and root domains, where `0` indicates no limit. Defaults to `30`.
 ///   - Long Flag: `--limit`
@@ -210,9 +285,28 @@ mod utils;
 ///   - Long Flag: `--showduplicates`
 ///
 /// - `showinvalids`:
-///   Lists invalid domains detected in the hosts list.
+///   Lists invalid entries detected in the hosts list, each with its line number,
+///   the raw line, and the reason it is invalid.
 ///   - Long Flag: `--invalid`
 ///
+/// - `showrewrites`:
+///   Lists entries which were rewritten to their canonical form: lowercase, without
+///   a trailing dot, and in punycode.
+///   - Long Flag: `--rewrites`
+///
+/// - `showredirects`:
+///   Lists entries which point to addresses other than null or loopback addresses.
+///   - Long Flag: `--redirects`
+///
+/// - `json`:
+///   Reports on the hosts list in JSON, including its invalid entries.
+///   - Long Flag: `--json`
+///
+/// - `redirect_policy`:
+///   When building, null-route (`rewrite`) or leave out (`reject`) entries which
+///   point to addresses other than null or loopback addresses. Defaults to `rewrite`.
+///   - Long Flag: `--redirect-policy`
+///
 /// - `sysclipboard`:
 ///   Uses the contents of the system clipboard as the `comparehosts` input.
 ///   - Long Flag: `--clip`
@@ -235,6 +329,44 @@ mod utils;
 ///   Disables caching to ensure fresh processing.
 ///   - Long Flag: `--skipcache`
 ///
+/// - `compresscache`:
+///   Stores lists gzipped in the cache, to save disk space. Cached lists are
+///   read whether compressed or not.
+///   - Long Flag: `--compress-cache`
+///
+/// - `base_url`:
+///   Fetches sources from a local folder or server in place of the web, a
+///   source at `https://host/path` from `<base>/host/path`. Such sources are
+///   not cached.
+///   - Long Flag: `--base-url`
+///
+/// - `timeout`:
+///   Seconds to wait for each source. Defaults to the `http.timeout` of
+///   rh.json, where `http.timeouts` may also set it for particular sources,
+///   else 30.
+///   - Long Flag: `--timeout`
+///
+/// - `retries`:
+///   How often to retry, with exponential backoff, a request which times out,
+///   fails to connect, or meets a 408, 429, 500, 502, 503, or 504 status.
+///   Defaults to the `http.retries` of rh.json, else 2.
+///   - Long Flag: `--retries`
+///
+/// - `proxy`:
+///   The proxy through which to fetch sources. Defaults to the `http.proxy` of
+///   rh.json, else the system's.
+///   - Long Flag: `--proxy`
+///
+/// - `user_agent`:
+///   The user agent with which to fetch sources. Defaults to the
+///   `http.user_agent` of rh.json, else `rh/<version>`.
+///   - Long Flag: `--user-agent`
+///
+/// - `ca_bundle`:
+///   A PEM file of root certificates to trust as well as the usual ones, as
+///   behind a corporate proxy. Defaults to the `http.ca_bundle` of rh.json.
+///   - Long Flag: `--ca-bundle`
+///
 #[derive(Debug, Default, Parser)]
 #[clap(author, version, about, long_about = None)]
 #[deny(missing_docs)]
@@ -246,7 +378,7 @@ pub struct Arguments {
         default_value = "base",
         help = r#"The main hosts file, the basis for comparison.
 
-A shortcut code, full URL, or a path to a local file.
+A shortcut code, full URL, or a path to a local file, or - for stdin.
 Use the -c option to specify a comparison list.
 Use the -clip option to use what is on the system clipboard
 
@@ -310,7 +442,7 @@ Source list shortcuts:
         short,
         long = "compare",
         help = r#"The hosts file to compare to the main hosts file
-A shortcut code, full URL, or a path to a local file.
+A shortcut code, full URL, or a path to a local file, or - for stdin.
 Use the -m option for the main comparison list.
 Use the -clip option to use what is on the system clipboard.
 
@@ -331,10 +463,18 @@ See the documentation for the -m flag for a list of shortcut codes
     #[clap(
         long = "ip",
         default_value = "0.0.0.0",
+        value_parser = parse_ip_address,
         help = "The ip address to use when listing hosts"
     )]
     iplocalhost: String,
 
+    #[clap(
+        long = "ipv6",
+        value_enum,
+        help = "Emit IPv6 null-route entries paired with, or instead of, the others"
+    )]
+    ipv6: Option<Ipv6Mode>,
+
     #[clap(
         short = 'd',
         long = "default_hosts",
@@ -363,6 +503,33 @@ See the documentation for the -m flag for a list of shortcut codes
     )]
     plain_output: bool,
 
+    #[clap(
+        long = "per-line",
+        value_parser = clap::value_parser!(u8).range(1..=9),
+        help = "Domains per line in hosts output, up to the Windows cap of 9"
+    )]
+    domains_per_line: Option<u8>,
+
+    #[clap(
+        long,
+        help = "Remove subdomains of blocked domains when the output format blocks subdomains"
+    )]
+    prune: bool,
+
+    #[clap(
+        long,
+        help = "Output internationalized domains in Unicode rather than punycode"
+    )]
+    unicode: bool,
+
+    #[clap(
+        long,
+        value_enum,
+        default_value = "hosts",
+        help = "The output format when listing domains"
+    )]
+    format: OutputFormat,
+
     #[clap(
         short,
         long,
@@ -396,6 +563,12 @@ See the documentation for the -m flag for a list of shortcut codes
     )]
     tld: bool,
 
+    #[clap(
+        long,
+        help = "Tally root domains and TLDs by label instead of with the Public Suffix List"
+    )]
+    naive: bool,
+
     #[clap(
         short,
         long,
@@ -412,10 +585,33 @@ See the documentation for the -m flag for a list of shortcut codes
 
     #[clap(
         long = "invalid",
-        help = "List invalid domains when reporting on a hosts list"
+        help = "List invalid entries, with line numbers and reasons, when reporting on a hosts list"
     )]
     showinvalids: bool,
 
+    #[clap(
+        long = "rewrites",
+        help = "List entries rewritten to canonical form when reporting on a hosts list"
+    )]
+    showrewrites: bool,
+
+    #[clap(
+        long = "redirects",
+        help = "List entries that redirect to non-null addresses when reporting on a hosts list"
+    )]
+    showredirects: bool,
+
+    #[clap(long, help = "Report on a hosts list in JSON")]
+    json: bool,
+
+    #[clap(
+        long = "redirect-policy",
+        value_enum,
+        default_value = "rewrite",
+        help = "When building, rewrite entries that redirect to non-null addresses, or reject them"
+    )]
+    redirect_policy: RedirectPolicy,
+
     #[clap(
         long = "clip",
         help = "Use the contents of the system clipboard as compare hosts"
@@ -437,6 +633,40 @@ See the documentation for the -m flag for a list of shortcut codes
 
     #[clap(long = "skipcache", help = "Do not use cache")]
     skipcache: bool,
+
+    #[clap(long = "compress-cache", help = "Store cached lists gzipped")]
+    compresscache: bool,
+
+    #[clap(
+        long = "base-url",
+        help = "Fetch sources from this folder or server instead, as <base>/<host>/<path>"
+    )]
+    base_url: Option<String>,
+
+    #[clap(long, value_name = "SECONDS", help = "Seconds to wait for each source")]
+    timeout: Option<u64>,
+
+    #[clap(
+        long,
+        help = "How often to retry a request which fails transiently, with exponential backoff"
+    )]
+    retries: Option<u32>,
+
+    #[clap(long, help = "The proxy through which to fetch sources")]
+    proxy: Option<String>,
+
+    #[clap(
+        long = "user-agent",
+        help = "The user agent with which to fetch sources"
+    )]
+    user_agent: Option<String>,
+
+    #[clap(
+        long = "ca-bundle",
+        value_name = "PEM",
+        help = "A PEM file of root certificates to trust, as well as the usual ones"
+    )]
+    ca_bundle: Option<String>,
 }
 
 impl Arguments {
@@ -464,6 +694,21 @@ pub enum Commands {
         #[clap(short, long)]
         /// The formula to build
         formula: Option<String>,
+        #[clap(long = "all-combinations")]
+        /// Build a product for every combination of tags, and write their summary
+        all_combinations: bool,
+        #[clap(long, default_value = ".")]
+        /// The folder into which products are built, and their summary written
+        root: String,
+        #[clap(long = "max-change", requires = "all_combinations")]
+        /// Fail when a source or product changes in size by more than this percentage since the previous build
+        max_change: Option<f64>,
+        #[clap(long = "max-change-domains", requires = "all_combinations")]
+        /// Fail when a source or product changes in size by more than this many domains since the previous build
+        max_change_domains: Option<usize>,
+        #[clap(long = "warn-only", requires = "all_combinations")]
+        /// Warn, rather than fail, when a change exceeds these limits
+        warn_only: bool,
     },
     /// Application cache initialize, prime, clear, or report.
     Cache {
@@ -471,8 +716,36 @@ pub enum Commands {
         #[clap(subcommand)]
         cacheaction: Option<cmd::cache::CacheCommands>,
     },
+    /// Rewrite a hosts file canonically, in place, keeping its comments
+    Fmt {
+        /// The local, uncompressed hosts file to rewrite
+        file: String,
+    },
     /// Initialize cache and templates
     Init,
+    /// Lint a hosts file, failing on duplicates, invalid domains, and mixed addresses
+    Lint {
+        /// The hosts file to lint
+        file: String,
+    },
+    /// Overlap between lists: intersections, Jaccard similarity, and unique domains
+    Overlap {
+        /// The shortcuts, tags, URLs, or files to compare
+        #[clap(required = true, num_args = 2..)]
+        lists: Vec<String>,
+        #[clap(long, value_enum, default_value = "table")]
+        /// The report format
+        report: ReportFormat,
+        #[clap(long)]
+        /// Count the domains in every exact combination of sources instead, as for an UpSet plot
+        upset: bool,
+    },
+    /// Check the health of the sources
+    Sources {
+        /// Sources subcommand
+        #[clap(subcommand)]
+        sourcesaction: Option<cmd::sources::SourcesCommands>,
+    },
     /// Display additional information about the application
     Info,
 }
@@ -558,8 +831,12 @@ async fn main() -> Result<(), Error> {
     let res = match &args.command {
         None => cmd::core::execute(args),
         Some(Commands::Init) => cmd::init::execute(args).await,
-        Some(Commands::Build { formula: _ }) => cmd::build::execute(args).await,
+        Some(Commands::Build { .. }) => cmd::build::execute(args).await,
         Some(Commands::Cache { cacheaction: _ }) => cmd::cache::execute(args).await,
+        Some(Commands::Fmt { .. }) => cmd::fmt::execute(args).await,
+        Some(Commands::Lint { .. }) => cmd::lint::execute(args).await,
+        Some(Commands::Overlap { .. }) => cmd::overlap::execute(args).await,
+        Some(Commands::Sources { .. }) => cmd::sources::execute(args).await,
         Some(Commands::Info) => show_info(args),
     };
 
@@ -600,3 +877,13 @@ fn test_default_command_line_arguments() {
         "Expected the stats argument to be Some(true)"
     );
 }
+
+#[test]
+fn test_a_dash_reads_hosts_from_stdin() {
+    let arguments = Arguments::parse_from(["rh", "-c", "-", "-m", "base"]);
+    assert_eq!(
+        arguments.comparehosts.as_deref(),
+        Some(types::STDIN),
+        "Expected a dash to be accepted as the compare hosts"
+    );
+}
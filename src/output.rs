@@ -0,0 +1,579 @@
+//! Output formats for hosts lists.
+//!
+//! Each format implements `HostsWriter`, and `write_hosts` handles the parts
+//! common to all of them: preambles, comment headers, and default hosts.
+use crate::{Arguments, types::Domain, utils::unicode_domain};
+use clap::ValueEnum;
+use std::io::{self, Write};
+use std::net::{IpAddr, Ipv6Addr};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The hosts we place at the top of hosts files when asked for defaults.
+pub const DEFAULT_HOSTS: [&str; 14] = [
+    "127.0.0.1 localhost",
+    "127.0.0.1 localhost.localdomain",
+    "127.0.0.1 local",
+    "255.255.255.255 broadcasthost",
+    "::1 localhost",
+    "::1 ip6-localhost",
+    "::1 ip6-loopback",
+    "fe80::1%lo0 localhost",
+    "ff00::0 ip6-localnet",
+    "ff00::0 ip6-mcastprefix",
+    "ff02::1 ip6-allnodes",
+    "ff02::2 ip6-allrouters",
+    "ff02::3 ip6-allhosts",
+    "0.0.0.0 0.0.0.0",
+];
+
+/// The formats in which we can write hosts lists.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// `0.0.0.0 example.com`
+    #[default]
+    Hosts,
+    /// `example.com`
+    Plain,
+    /// `address=/example.com/0.0.0.0`
+    Dnsmasq,
+    /// `local-zone: "example.com" always_nxdomain`
+    Unbound,
+    /// BIND response policy zone, `example.com CNAME .`
+    Rpz,
+    /// AdBlock and uBlock filters, `||example.com^`
+    Adblock,
+    /// Pi-hole gravity import, one domain per line
+    Pihole,
+}
+
+/// The formats in which we can write reports, as opposed to hosts lists.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ReportFormat {
+    /// Aligned columns, for people
+    #[default]
+    Table,
+    /// Comma-separated values, for spreadsheets
+    Csv,
+    /// JSON, for programs
+    Json,
+}
+
+/// A CSV field, quoted when it has to be.
+pub fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// How IPv6 null-route entries accompany IPv4 ones.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Ipv6Mode {
+    /// An `::` entry alongside each IPv4 entry.
+    Paired,
+    /// Only `::` entries.
+    Only,
+}
+
+/// The IPv6 null-route address.
+pub const IPV6_NULL_ROUTE: &str = "::";
+
+/// The addresses for each entry, according to the IPv6 mode. An `ip` which
+/// is itself the IPv6 null route needs no pair.
+fn addresses(ip: &str, ipv6: Option<Ipv6Mode>) -> Vec<&str> {
+    let is_ipv6_null_route = ip.parse::<IpAddr>() == Ok(IpAddr::V6(Ipv6Addr::UNSPECIFIED));
+    match ipv6 {
+        None => vec![ip],
+        Some(Ipv6Mode::Paired) if is_ipv6_null_route => vec![ip],
+        Some(Ipv6Mode::Paired) => vec![ip, IPV6_NULL_ROUTE],
+        Some(Ipv6Mode::Only) => vec![IPV6_NULL_ROUTE],
+    }
+}
+
+/// Writes domains in a given output format.
+pub trait HostsWriter {
+    /// The marker which begins a comment line.
+    fn comment(&self) -> &'static str {
+        "#"
+    }
+
+    /// Lines which must come first in the output, before any comments.
+    fn preamble(&self) -> Vec<String> {
+        vec![]
+    }
+
+    /// Default entries placed above the domains, if any.
+    fn defaults(&self) -> Vec<String> {
+        vec![]
+    }
+
+    /// The lines for a single domain.
+    fn entry(&self, domain: &str) -> Vec<String>;
+
+    /// The lines for a run of domains.
+    fn entries(&self, domains: &[Domain]) -> Vec<String> {
+        domains.iter().flat_map(|d| self.entry(d)).collect()
+    }
+
+    /// Whether blocking a domain also blocks its subdomains.
+    fn blocks_subdomains(&self) -> bool {
+        false
+    }
+}
+
+pub struct Hosts {
+    pub ip: String,
+    pub ipv6: Option<Ipv6Mode>,
+    /// Domains per line, which Windows caps at 9.
+    pub per_line: usize,
+}
+
+impl HostsWriter for Hosts {
+    fn defaults(&self) -> Vec<String> {
+        DEFAULT_HOSTS.iter().map(|h| h.to_string()).collect()
+    }
+
+    fn entry(&self, domain: &str) -> Vec<String> {
+        addresses(&self.ip, self.ipv6)
+            .iter()
+            .map(|ip| format!("{} {}", ip, domain))
+            .collect()
+    }
+
+    fn entries(&self, domains: &[Domain]) -> Vec<String> {
+        let mut lines = vec![];
+        for chunk in domains.chunks(self.per_line.max(1)) {
+            for ip in addresses(&self.ip, self.ipv6) {
+                lines.push(format!("{} {}", ip, chunk.join(" ")));
+            }
+        }
+        lines
+    }
+}
+
+pub struct Plain;
+
+impl HostsWriter for Plain {
+    fn entry(&self, domain: &str) -> Vec<String> {
+        vec![domain.to_string()]
+    }
+}
+
+pub struct Dnsmasq {
+    pub ip: String,
+    pub ipv6: Option<Ipv6Mode>,
+}
+
+impl HostsWriter for Dnsmasq {
+    fn entry(&self, domain: &str) -> Vec<String> {
+        addresses(&self.ip, self.ipv6)
+            .iter()
+            .map(|ip| format!("address=/{}/{}", domain, ip))
+            .collect()
+    }
+
+    fn blocks_subdomains(&self) -> bool {
+        true
+    }
+}
+
+pub struct Unbound;
+
+impl HostsWriter for Unbound {
+    fn preamble(&self) -> Vec<String> {
+        vec!["server:".to_string()]
+    }
+
+    fn entry(&self, domain: &str) -> Vec<String> {
+        vec![format!("local-zone: \"{}\" always_nxdomain", domain)]
+    }
+
+    fn blocks_subdomains(&self) -> bool {
+        true
+    }
+}
+
+pub struct Rpz {
+    pub serial: u64,
+}
+
+impl HostsWriter for Rpz {
+    fn comment(&self) -> &'static str {
+        ";"
+    }
+
+    fn preamble(&self) -> Vec<String> {
+        vec![
+            "$TTL 2h".to_string(),
+            format!(
+                "@ IN SOA localhost. root.localhost. ({} 6h 1h 1w 2h)",
+                self.serial
+            ),
+            "  IN NS localhost.".to_string(),
+        ]
+    }
+
+    fn entry(&self, domain: &str) -> Vec<String> {
+        vec![
+            format!("{} CNAME .", domain),
+            format!("*.{} CNAME .", domain),
+        ]
+    }
+
+    fn blocks_subdomains(&self) -> bool {
+        true
+    }
+}
+
+pub struct Adblock;
+
+impl HostsWriter for Adblock {
+    fn comment(&self) -> &'static str {
+        "!"
+    }
+
+    fn preamble(&self) -> Vec<String> {
+        vec!["[Adblock Plus 2.0]".to_string()]
+    }
+
+    fn entry(&self, domain: &str) -> Vec<String> {
+        vec![format!("||{}^", domain)]
+    }
+
+    fn blocks_subdomains(&self) -> bool {
+        true
+    }
+}
+
+pub struct Pihole;
+
+impl HostsWriter for Pihole {
+    fn entry(&self, domain: &str) -> Vec<String> {
+        vec![domain.to_string()]
+    }
+}
+
+/// The output format chosen by the arguments. `--plain` wins over `--format`.
+pub fn get_format(args: &Arguments) -> OutputFormat {
+    if args.plain_output {
+        OutputFormat::Plain
+    } else {
+        args.format
+    }
+}
+
+/// The writer for the output format chosen by the arguments.
+pub fn get_writer(args: &Arguments) -> Box<dyn HostsWriter> {
+    match get_format(args) {
+        OutputFormat::Hosts => Box::new(Hosts {
+            ip: args.iplocalhost.clone(),
+            ipv6: args.ipv6,
+            per_line: args.domains_per_line.map_or(1, usize::from),
+        }),
+        OutputFormat::Plain => Box::new(Plain),
+        OutputFormat::Dnsmasq => Box::new(Dnsmasq {
+            ip: args.iplocalhost.clone(),
+            ipv6: args.ipv6,
+        }),
+        OutputFormat::Unbound => Box::new(Unbound),
+        OutputFormat::Rpz => Box::new(Rpz {
+            serial: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(1),
+        }),
+        OutputFormat::Adblock => Box::new(Adblock),
+        OutputFormat::Pihole => Box::new(Pihole),
+    }
+}
+
+/// Domains, and the comment lines which introduce them in their source.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Group {
+    /// Whether a blank line separates this group from what precedes it.
+    pub blank_before: bool,
+    pub comments: Vec<String>,
+    pub domains: Vec<Domain>,
+}
+
+/// Write a hosts list in the format chosen by the arguments.
+///
+/// The `header` lines are written as comments unless `--skipheaders` is
+/// given, default hosts are added with `--default_hosts` for formats that
+/// have them, and domains are written in Unicode with `--unicode`.
+pub fn write_hosts(
+    writer: &mut dyn Write,
+    args: &Arguments,
+    header: &[String],
+    domains: &[Domain],
+) -> io::Result<()> {
+    let group = Group {
+        domains: domains.to_vec(),
+        ..Default::default()
+    };
+    write_groups(writer, args, header, &[group])
+}
+
+/// Write a hosts list like `write_hosts`, with each group of domains under
+/// its comments. Comments are omitted with `--skipheaders`, and so are groups
+/// left with no domains.
+pub fn write_groups(
+    writer: &mut dyn Write,
+    args: &Arguments,
+    header: &[String],
+    groups: &[Group],
+) -> io::Result<()> {
+    let hw = get_writer(args);
+    for line in hw.preamble() {
+        writeln!(writer, "{}", line)?;
+    }
+    if !args.skipheaders {
+        write_comments(writer, hw.as_ref(), header)?;
+    }
+    if args.adddefaults {
+        for line in hw.defaults() {
+            writeln!(writer, "{}", line)?;
+        }
+    }
+    for group in groups.iter().filter(|g| !g.domains.is_empty()) {
+        if !args.skipheaders && !group.comments.is_empty() {
+            if group.blank_before {
+                writeln!(writer)?;
+            }
+            write_comments(writer, hw.as_ref(), &group.comments)?;
+        }
+        let unicode: Vec<Domain>;
+        let domains = if args.unicode {
+            unicode = group.domains.iter().map(|d| unicode_domain(d)).collect();
+            &unicode
+        } else {
+            &group.domains
+        };
+        for line in hw.entries(domains) {
+            writeln!(writer, "{}", line)?;
+        }
+    }
+    Ok(())
+}
+
+/// Write `#` comment lines with the format's comment marker.
+fn write_comments(
+    writer: &mut dyn Write,
+    hw: &dyn HostsWriter,
+    lines: &[String],
+) -> io::Result<()> {
+    for line in lines {
+        let text = line.trim_start_matches('#').trim();
+        if text.is_empty() {
+            writeln!(writer, "{}", hw.comment())?;
+        } else {
+            writeln!(writer, "{} {}", hw.comment(), text)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(args: &Arguments) -> String {
+        let mut out: Vec<u8> = vec![];
+        let header = vec!["# Title: test".to_string(), "#".to_string()];
+        let domains = vec!["example.com".to_string(), "www.example.org".to_string()];
+        write_hosts(&mut out, args, &header, &domains).expect("write failed");
+        String::from_utf8(out).expect("invalid utf-8")
+    }
+
+    #[test]
+    fn test_hosts_format_is_the_default() {
+        let args = Arguments::new();
+        assert_eq!(
+            render(&args),
+            "# Title: test\n#\n0.0.0.0 example.com\n0.0.0.0 www.example.org\n"
+        );
+    }
+
+    #[test]
+    fn test_plain_flag_overrides_format() {
+        let args = Arguments {
+            plain_output: true,
+            format: OutputFormat::Dnsmasq,
+            skipheaders: true,
+            ..Arguments::new()
+        };
+        assert_eq!(render(&args), "example.com\nwww.example.org\n");
+    }
+
+    #[test]
+    fn test_dnsmasq_unbound_and_adblock_formats() {
+        let mut args = Arguments {
+            skipheaders: true,
+            ..Arguments::new()
+        };
+        args.format = OutputFormat::Dnsmasq;
+        assert_eq!(
+            render(&args),
+            "address=/example.com/0.0.0.0\naddress=/www.example.org/0.0.0.0\n"
+        );
+        args.format = OutputFormat::Unbound;
+        assert_eq!(
+            render(&args),
+            "server:\nlocal-zone: \"example.com\" always_nxdomain\nlocal-zone: \"www.example.org\" always_nxdomain\n"
+        );
+        args.format = OutputFormat::Adblock;
+        assert_eq!(
+            render(&args),
+            "[Adblock Plus 2.0]\n||example.com^\n||www.example.org^\n"
+        );
+    }
+
+    #[test]
+    fn test_rpz_format_comments_and_entries() {
+        let args = Arguments {
+            format: OutputFormat::Rpz,
+            ..Arguments::new()
+        };
+        let rendered = render(&args);
+        assert!(rendered.starts_with("$TTL 2h\n@ IN SOA "));
+        assert!(rendered.contains("; Title: test\n;\n"));
+        assert!(rendered.contains("example.com CNAME .\n*.example.com CNAME .\n"));
+    }
+
+    #[test]
+    fn test_ipv6_entries_are_paired_or_alone() {
+        let mut args = Arguments {
+            skipheaders: true,
+            ipv6: Some(Ipv6Mode::Paired),
+            ..Arguments::new()
+        };
+        assert_eq!(
+            render(&args),
+            "0.0.0.0 example.com\n:: example.com\n0.0.0.0 www.example.org\n:: www.example.org\n"
+        );
+        args.ipv6 = Some(Ipv6Mode::Only);
+        assert_eq!(render(&args), ":: example.com\n:: www.example.org\n");
+        // an IPv6 null route is not paired with itself
+        args.ipv6 = Some(Ipv6Mode::Paired);
+        args.iplocalhost = "::".to_string();
+        assert_eq!(render(&args), ":: example.com\n:: www.example.org\n");
+        args.ipv6 = Some(Ipv6Mode::Only);
+        args.format = OutputFormat::Dnsmasq;
+        assert_eq!(
+            render(&args),
+            "address=/example.com/::\naddress=/www.example.org/::\n"
+        );
+    }
+
+    #[test]
+    fn test_hosts_format_packs_domains_per_line() {
+        let mut out: Vec<u8> = vec![];
+        let mut args = Arguments {
+            skipheaders: true,
+            domains_per_line: Some(2),
+            ..Arguments::new()
+        };
+        let domains: Vec<Domain> = ["a.com", "b.com", "c.com"]
+            .iter()
+            .map(|d| d.to_string())
+            .collect();
+        write_hosts(&mut out, &args, &[], &domains).expect("write failed");
+        assert_eq!(
+            String::from_utf8(out).expect("invalid utf-8"),
+            "0.0.0.0 a.com b.com\n0.0.0.0 c.com\n"
+        );
+
+        let mut out: Vec<u8> = vec![];
+        args.ipv6 = Some(Ipv6Mode::Paired);
+        write_hosts(&mut out, &args, &[], &domains).expect("write failed");
+        assert_eq!(
+            String::from_utf8(out).expect("invalid utf-8"),
+            "0.0.0.0 a.com b.com\n:: a.com b.com\n0.0.0.0 c.com\n:: c.com\n"
+        );
+    }
+
+    #[test]
+    fn test_only_suffix_blocking_formats_block_subdomains() {
+        let mut args = Arguments::new();
+        assert!(!get_writer(&args).blocks_subdomains());
+        for format in [
+            OutputFormat::Dnsmasq,
+            OutputFormat::Unbound,
+            OutputFormat::Rpz,
+            OutputFormat::Adblock,
+        ] {
+            args.format = format;
+            assert!(get_writer(&args).blocks_subdomains());
+        }
+        args.format = OutputFormat::Pihole;
+        assert!(!get_writer(&args).blocks_subdomains());
+    }
+
+    #[test]
+    fn test_unicode_flag_writes_internationalized_domains_in_unicode() {
+        let mut out: Vec<u8> = vec![];
+        let args = Arguments {
+            skipheaders: true,
+            unicode: true,
+            ..Arguments::new()
+        };
+        let domains = vec!["xn--bcher-kva.example".to_string()];
+        write_hosts(&mut out, &args, &[], &domains).expect("write failed");
+        assert_eq!(
+            String::from_utf8(out).expect("invalid utf-8"),
+            "0.0.0.0 bücher.example\n"
+        );
+    }
+
+    #[test]
+    fn test_default_hosts_are_only_added_to_hosts_format() {
+        let mut args = Arguments {
+            adddefaults: true,
+            skipheaders: true,
+            ..Arguments::new()
+        };
+        assert!(render(&args).starts_with("127.0.0.1 localhost\n"));
+        args.format = OutputFormat::Pihole;
+        assert_eq!(render(&args), "example.com\nwww.example.org\n");
+    }
+
+    #[test]
+    fn test_groups_are_written_under_their_comments() {
+        let groups = vec![
+            Group {
+                blank_before: true,
+                comments: vec!["# Ads".to_string()],
+                domains: vec!["ads.example.com".to_string()],
+            },
+            Group {
+                comments: vec!["# Nothing left".to_string()],
+                ..Default::default()
+            },
+            Group {
+                comments: vec!["# Trackers".to_string()],
+                domains: vec!["t.example.org".to_string()],
+                ..Default::default()
+            },
+        ];
+        let header = vec!["# Title: test".to_string()];
+        let mut args = Arguments {
+            format: OutputFormat::Adblock,
+            ..Arguments::new()
+        };
+        let mut out: Vec<u8> = vec![];
+        write_groups(&mut out, &args, &header, &groups).expect("write failed");
+        assert_eq!(
+            String::from_utf8(out).expect("invalid utf-8"),
+            "[Adblock Plus 2.0]\n! Title: test\n\n! Ads\n||ads.example.com^\n! Trackers\n||t.example.org^\n"
+        );
+
+        args.skipheaders = true;
+        let mut out: Vec<u8> = vec![];
+        write_groups(&mut out, &args, &header, &groups).expect("write failed");
+        assert_eq!(
+            String::from_utf8(out).expect("invalid utf-8"),
+            "[Adblock Plus 2.0]\n||ads.example.com^\n||t.example.org^\n"
+        );
+    }
+}
@@ -0,0 +1,90 @@
+//! Responses which are not hosts files.
+//!
+//! An upstream in trouble may answer with an HTML error or login page, and a
+//! `200` status. Parsed as a hosts file, such a page yields a few bogus
+//! domains and a pile of invalid entries. We sniff bodies for this before
+//! they are used, or cached.
+
+/// Where markup is looked for at the start of a body.
+const SNIFF_LENGTH: usize = 1024;
+
+/// Markers of an HTML or XML document.
+const MARKUP: [&str; 5] = ["<!doctype", "<html", "<head", "<body", "<?xml"];
+
+/// The fewest invalid entries which can condemn a body.
+const MIN_INVALIDS: usize = 10;
+
+/// The largest share of entries which may be invalid.
+const MAX_INVALID_RATIO: f64 = 0.5;
+
+/// Why a body, given its `Content-Type` if any, looks like a web page rather
+/// than a hosts file, or `None` when it doesn't.
+pub fn sniff_markup(content_type: Option<&str>, text: &str) -> Option<String> {
+    if let Some(content_type) = content_type {
+        let content_type = content_type.trim().to_ascii_lowercase();
+        if content_type.starts_with("text/html") || content_type.contains("xhtml") {
+            return Some(format!("content type {}", content_type));
+        }
+    }
+    let start: String = text
+        .trim_start()
+        .chars()
+        .take(SNIFF_LENGTH)
+        .collect::<String>()
+        .to_ascii_lowercase();
+    if start.starts_with('<')
+        && let Some(marker) = MARKUP.iter().find(|m| start.contains(*m))
+    {
+        return Some(format!("{}> markup", marker));
+    }
+    None
+}
+
+/// Why a body with these counts of valid domains and invalid entries looks
+/// like garbage, or `None` when it doesn't.
+pub fn sniff_entries(domains: usize, invalids: usize) -> Option<String> {
+    let ratio = invalids as f64 / (domains + invalids).max(1) as f64;
+    if invalids >= MIN_INVALIDS && ratio > MAX_INVALID_RATIO {
+        Some(format!(
+            "{} of {} entries are invalid",
+            invalids,
+            domains + invalids
+        ))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sniff_markup_flags_html_by_content_type_or_markers() {
+        let hosts = "# Title: test\n0.0.0.0 example.com\n";
+        assert_eq!(sniff_markup(Some("text/plain; charset=utf-8"), hosts), None);
+        assert_eq!(sniff_markup(None, hosts), None);
+        assert_eq!(
+            sniff_markup(Some("text/html; charset=utf-8"), hosts),
+            Some("content type text/html; charset=utf-8".to_string())
+        );
+        assert_eq!(
+            sniff_markup(None, "\n  <!DOCTYPE html>\n<html><body>404</body></html>"),
+            Some("<!doctype> markup".to_string())
+        );
+        assert!(sniff_markup(None, "<html lang=\"en\">").is_some());
+        // an angle bracket alone is not markup
+        assert_eq!(sniff_markup(None, "<not a page>\n0.0.0.0 a.com"), None);
+    }
+
+    #[test]
+    fn test_sniff_entries_flags_mostly_invalid_bodies() {
+        assert_eq!(sniff_entries(1_000, 50), None);
+        // a few invalid entries in a small list are not enough
+        assert_eq!(sniff_entries(1, 5), None);
+        assert_eq!(
+            sniff_entries(3, 40),
+            Some("40 of 43 entries are invalid".to_string())
+        );
+    }
+}
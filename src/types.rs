@@ -1,32 +1,153 @@
-use indexmap::IndexSet;
+use indexmap::{IndexMap, IndexSet};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fmt,
     fmt::Display,
-    fs::File,
-    io::{BufReader, prelude::*},
-    path::Path,
+    fs,
+    io::{self, prelude::*},
+    path::{Path, PathBuf},
 };
 // See also [Rust: Domain Name Validation](https://bas-man.dev/post/rust/domain-name-validation/)
 use crate::Arguments;
-use crate::utils::{is_domain, norm_string, trim_inline_comments};
-use crate::{cmd::cache, config::get_shortcuts};
-use futures::executor::block_on;
+use crate::compression::decompress;
+use crate::document::Document;
+use crate::fetch::{self, Fetcher};
+use crate::input::{InputFormat, detect_format, to_hosts_line};
+use crate::sniff::{sniff_entries, sniff_markup};
+use crate::utils::{
+    InvalidReason, canonical_domain, effective_tld, invalid_reason, is_domain, is_null_route,
+    norm_string, root_domain, trim_inline_comments,
+};
+use crate::{
+    cmd::cache,
+    config::{get_shortcuts, get_source_spec_by_url},
+};
+use anyhow::{Context, bail};
+use clap::ValueEnum;
 use num_format::{Locale, ToFormattedString};
+use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
+use std::net::IpAddr;
+
+/// The source name which reads from standard input.
+pub const STDIN: &str = "-";
 
 pub type Domain = String;
 pub type Domains = IndexSet<Domain>;
 pub type IPaddress = String;
+/// Entries, as found, mapped to their canonical domains.
+pub type Rewrites = IndexMap<String, Domain>;
+/// Normalized lines mapped to the line number where each first appears.
+pub type LineNumbers = IndexMap<String, usize>;
 pub type Tag = String;
 pub type Tags = Vec<Tag>;
 // pub type IPaddress = String;
 
-#[allow(dead_code)]
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
 pub struct Host {
-    ip_address: IPaddress,
-    domain: Domain,
+    pub ip_address: IPaddress,
+    pub domain: Domain,
+}
+
+pub type Hosts = Vec<Host>;
+
+/// An entry we could not accept as a domain, and where we found it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct InvalidEntry {
+    /// The 1-based line number in the source, or 0 when unknown.
+    pub line: usize,
+    /// The source line, as found.
+    pub raw: String,
+    pub entry: String,
+    pub reason: InvalidReason,
+}
+
+pub type InvalidEntries = Vec<InvalidEntry>;
+
+/// What to do, when building, with entries that redirect to real addresses.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum RedirectPolicy {
+    /// Null-route them like every other entry.
+    #[default]
+    Rewrite,
+    /// Leave them out.
+    Reject,
+}
+
+/// Domain aliases which are often found in hosts files which we do not want
+/// to flag as formally invalid.
+pub const HEADER_TOKENS: [&str; 17] = [
+    "::1",
+    "broadcasthost",
+    "fe80::1%lo0",
+    "ff00::0",
+    "ff02::1",
+    "ff02::2",
+    "ff02::3",
+    "ip6-allhosts",
+    "ip6-allnodes",
+    "ip6-allrouters",
+    "ip6-localhost",
+    "ip6-localnet",
+    "ip6-loopback",
+    "ip6-mcastprefix",
+    "local",
+    "localhost",
+    "localhost.localdomain",
+];
+
+/// Those of `domains` already covered by another of them, a parent domain,
+/// as `ads.example.com` is by `example.com`.
+pub fn covered_subdomains(domains: &Domains) -> Domains {
+    let mut covered = Domains::new();
+    for domain in domains {
+        let mut rest = domain.as_str();
+        while let Some((_, parent)) = rest.split_once('.') {
+            if domains.contains(parent) {
+                covered.insert(domain.to_owned());
+                break;
+            }
+            rest = parent;
+        }
+    }
+    covered
+}
+
+/// The order in which `--sort` lists domains: by root domain, then by
+/// subdomain, then by TLD.
+pub fn domain_order(a: &str, b: &str) -> Ordering {
+    // Function to parse a domain into components: (subdomain, root domain, TLD)
+    fn parse_domain(domain: &str) -> Vec<String> {
+        let parts: Vec<&str> = domain.split('.').collect();
+        let tld = parts.last().unwrap().to_string(); // Get TLD
+        let root = parts.get(parts.len() - 2).unwrap_or(&"").to_string(); // Get root domain
+        let subdomain = parts[..parts.len() - 2].join("."); // Join remaining parts as subdomain
+
+        let mut r = vec![subdomain, root, tld];
+        // If there are no subdomains, push an empty string
+        if r[0].is_empty() {
+            r[0] = "".to_string();
+        }
+        r
+    }
+
+    let a_parts = parse_domain(a);
+    let b_parts = parse_domain(b);
+
+    // Compare by root domain and TLD first
+    match a_parts[1].cmp(&b_parts[1]) {
+        Ordering::Equal => {
+            // Then compare by first-level subdomain
+            match a_parts[0].cmp(&b_parts[0]) {
+                Ordering::Equal => {
+                    // Finally, compare remaining subdomains
+                    a_parts[2..].cmp(&b_parts[2..])
+                }
+                other => other,
+            }
+        }
+        other => other,
+    }
 }
 
 // Source: https://users.rust-lang.org/t/structs-with-similar-fields/99065/4
@@ -40,8 +161,11 @@ macro_rules! with_hosts_collection_shared_fields_and_impl {
             pub raw_list: Vec<Domain>,
             pub front_matter: Vec<String>,
             pub domains: Domains,
+            pub hosts: Hosts,
             pub duplicates: Domains,
             pub invalids: Domains,
+            pub invalid_entries: InvalidEntries,
+            pub rewrites: Rewrites,
             pub args: Arguments,
             $(pub $field_name: $field_type,)*
         }
@@ -61,16 +185,40 @@ macro_rules! with_hosts_collection_shared_fields_and_impl {
                         // self.duplicates.len().to_formatted_string(&Locale::en),
                         // self.invalids.len().to_formatted_string(&Locale::en)
                     )?;
+                    write!(f, "{}", self.sources_table())?;
                     if self.args.showduplicates && self.duplicates.len() > 0 {
                         writeln!(f, "Duplicates list:")?;
                         for dup in &self.duplicates {
                             writeln!(f, "{}", dup)?;
                         }
                     }
-                    if self.args.showinvalids && self.invalids.len() > 0 {
-                        writeln!(f, "Invalids list:")?;
-                        for invalid in &self.invalids {
-                            writeln!(f, "{}", invalid)?;
+                    if self.args.showinvalids && self.invalid_entries.len() > 0 {
+                        writeln!(f, "Invalids list ({} distinct entries):", self.invalids.len().to_formatted_string(&Locale::en))?;
+                        writeln!(f, "{:>6}  {:<30}  {:<27}  {}", "Line", "Entry", "Reason", "Raw line")?;
+                        for invalid in &self.invalid_entries {
+                            writeln!(f, "{:>6}  {:<30}  {:<27}  {}", invalid.line, invalid.entry, invalid.reason.to_string(), invalid.raw)?;
+                        }
+                    }
+                    if self.args.showrewrites && self.rewrites.len() > 0 {
+                        writeln!(f, "Rewritten entries:")?;
+                        for (entry, domain) in &self.rewrites {
+                            writeln!(f, "{} -> {}", entry, domain)?;
+                        }
+                    }
+                    if self.args.prune {
+                        writeln!(
+                            f,
+                            "Subdomains covered by a blocked parent: {}",
+                            self.covered_subdomains().len().to_formatted_string(&Locale::en)
+                        )?;
+                    }
+                    if self.args.showredirects {
+                        let redirects = self.redirects();
+                        if redirects.len() > 0 {
+                            writeln!(f, "Redirections to non-null addresses:")?;
+                            for host in redirects {
+                                writeln!(f, "{} {}", host.ip_address, host.domain)?;
+                            }
                         }
                     }
 
@@ -121,13 +269,28 @@ macro_rules! with_hosts_collection_shared_fields_and_impl {
         }
 
         impl $name {
+            /// Entries which point somewhere other than a null or loopback address.
+            pub fn redirects(&self) -> Hosts {
+                self.hosts
+                    .iter()
+                    .filter(|h| !is_null_route(&h.ip_address))
+                    .cloned()
+                    .collect()
+            }
+
+            /// Domains already covered by a blocked parent domain, as
+            /// `ads.example.com` is by `example.com`.
+            pub fn covered_subdomains(&self) -> Domains {
+                covered_subdomains(&self.domains)
+            }
+
             pub fn tld(&self)  -> Vec<(Domain, u32)> {
                 // Step 1: Extract TLDs and count occurrences
                 let mut count: HashMap<Domain, u32> = HashMap::new();
                 for domain in &self.domains {
-                    // Split the domain by '.' and get the last part
-                    if let Some(tld) = domain.rsplit('.').next() {
-                        *count.entry(tld.to_lowercase()).or_insert(0) += 1;
+                    // The effective TLD, or the last label when naive
+                    if let Some(tld) = effective_tld(domain, self.args.naive) {
+                        *count.entry(tld).or_insert(0) += 1;
                     }
                 }
 
@@ -150,12 +313,9 @@ macro_rules! with_hosts_collection_shared_fields_and_impl {
                 // Step 1: Extract TLDs and count occurrences
                 let mut count: HashMap<Domain, u32> = HashMap::new();
                 for domain in &self.domains {
-                    // Split the domain by '.' and get the last two parts
-                    let parts: Vec<&str> = domain.split('.').collect();
-                    if parts.len() >= 2 {
-                        // Join the last two segments to form the root domain
-                        let rootdomain = format!("{}.{}", parts[parts.len() - 2], parts[parts.len() - 1]);
-                        *count.entry(rootdomain.to_lowercase()).or_insert(0) += 1;
+                    // The registrable domain, or the last two labels when naive
+                    if let Some(rootdomain) = root_domain(domain, self.args.naive) {
+                        *count.entry(rootdomain).or_insert(0) += 1;
                     }
                 }
 
@@ -240,41 +400,8 @@ macro_rules! with_hosts_collection_shared_fields_and_impl {
             }
 
             pub fn sorteddomains(&self)  -> Vec<Domain> {
-                // Function to parse a domain into components: (subdomain, root domain, TLD)
-                fn parse_domain(domain: &str) -> Vec<String> {
-                    let parts: Vec<&str> = domain.split('.').collect();
-                    let tld = parts.last().unwrap().to_string(); // Get TLD
-                    let root = parts.get(parts.len() - 2).unwrap_or(&"").to_string(); // Get root domain
-                    let subdomain = parts[..parts.len() - 2].join("."); // Join remaining parts as subdomain
-
-                    let mut r = vec![subdomain, root, tld];
-                    // If there are no subdomains, push an empty string
-                    if r[0].is_empty() {
-                        r[0] = "".to_string();
-                    }
-                    r
-                }
-
                 let mut v: Vec<Domain> = self.domains.clone().into_iter().collect();
-                v.sort_by(|a, b| {
-                    let a_parts = parse_domain(a);
-                    let b_parts = parse_domain(b);
-
-                    // Compare by root domain and TLD first
-                    match a_parts[1].cmp(&b_parts[1]) {
-                        Ordering::Equal => {
-                            // Then compare by first-level subdomain
-                            match a_parts[0].cmp(&b_parts[0]) {
-                                Ordering::Equal => {
-                                    // Finally, compare remaining subdomains
-                                    a_parts[2..].cmp(&b_parts[2..])
-                                }
-                                other => other,
-                            }
-                        }
-                        other => other,
-                    }
-                });
+                v.sort_by(|a, b| domain_order(a, b));
                 v
             }
         }
@@ -292,7 +419,10 @@ macro_rules! with_hosts_collection_shared_fields_and_impl {
 
 with_hosts_collection_shared_fields_and_impl!(
     #[derive(Debug, Default, Clone)]
-    struct Hostssource {}
+    struct Hostssource {
+        format: InputFormat,
+        line_numbers: LineNumbers,
+    }
 );
 
 pub trait Comparable: Display + Send + Sync {
@@ -325,6 +455,7 @@ pub trait Comparable: Display + Send + Sync {
 pub type Hostssources = Vec<Hostssource>;
 
 impl Hostssource {
+    #[allow(dead_code)]
     pub async fn new(location: impl Into<String>, name: impl Into<String>) -> Hostssource {
         let mut hs = Hostssource {
             _name: name.into(),
@@ -336,6 +467,16 @@ impl Hostssource {
     }
 
     pub async fn load(&mut self, src: &str) -> anyhow::Result<()> {
+        self.load_with(src, None).await
+    }
+
+    /// Load, fetching a URL with `fetcher` when given, so that many sources
+    /// share one, else with a fetcher built for this source's arguments.
+    pub async fn load_with(
+        &mut self,
+        src: &str,
+        fetcher: Option<&dyn Fetcher>,
+    ) -> anyhow::Result<()> {
         let mut actualsrc = src;
         // check if src is a shortcut
         let shortcuts = get_shortcuts();
@@ -348,8 +489,22 @@ impl Hostssource {
         }
 
         let normalizedsrc = actualsrc.to_lowercase();
+        // a freshly downloaded body, cached only once it passes muster
+        let mut fetched: Option<String> = None;
+        // the cache entry the body was read from, if any
+        let mut cached: Option<PathBuf> = None;
+        // sources served from a base URL stand in for the real ones, so
+        // they are neither read from nor written to the cache
+        let use_cache = self.args.base_url.is_none();
 
-        if actualsrc.contains('\n') {
+        if actualsrc == STDIN {
+            // standard input, as in `curl ... | rh -c -`
+            let mut bytes = vec![];
+            io::stdin().read_to_end(&mut bytes)?;
+            let text = decompress(bytes, None)?;
+            self.raw_list = text.lines().map(|l| l.to_string()).collect();
+            self.location = "stdin".to_string();
+        } else if actualsrc.contains('\n') {
             // if it's a list of domains
             self.raw_list = actualsrc
                 .trim()
@@ -361,47 +516,126 @@ impl Hostssource {
             // if it's a URL
             // check the cache
             let cache_file = cache::get(normalizedsrc.clone()).await;
-            if !self.args.skipcache && cache_file.is_some() {
+            if !self.args.skipcache && use_cache && cache_file.is_some() {
                 // read the cache
                 if self.args.verbose {
                     println!("==> Loading from cache: {}", src);
                 }
-                let file = File::open(cache_file.unwrap())
+                let cache_file = cache_file.unwrap();
+                // cached bodies may be compressed
+                let bytes = fs::read(&cache_file)
                     .unwrap_or_else(|_| panic!("File does not exist: {}", actualsrc));
-                let buf = BufReader::new(file);
-                self.raw_list = buf
-                    .lines()
-                    .map(|l| l.expect("Could not parse line"))
-                    .collect();
+                let text = decompress(bytes, None)?;
+                if let Some(reason) = sniff_markup(None, &text) {
+                    _ = fs::remove_file(&cache_file);
+                    bail!(
+                        "The cached copy of {} is not a hosts file ({}), so it was removed from the cache.",
+                        self.location,
+                        reason
+                    );
+                }
+                self.raw_list = text.lines().map(|l| l.to_string()).collect();
+                cached = Some(cache_file);
             } else {
                 // if no cache
                 if self.args.verbose {
                     println!("==> Loading over HTTP(S): {}", src);
                 }
-                let resp = reqwest::blocking::get(actualsrc).expect("request failed");
-                let body = resp.text().expect("body invalid");
-                self.raw_list = body.clone().lines().map(|l| l.to_string()).collect();
-                // submit to cache
-                _ = cache::set(normalizedsrc.clone(), body);
+                let resp = match fetcher {
+                    Some(fetcher) => fetcher.fetch(actualsrc)?,
+                    None => fetch::fetcher(&self.args)?.fetch(actualsrc)?,
+                };
+                if !resp.is_success() {
+                    bail!("{} returned HTTP status {}.", self.location, resp.status);
+                }
+                let body = decompress(resp.body, resp.content_encoding.as_deref())?;
+                if let Some(reason) = sniff_markup(resp.content_type.as_deref(), &body) {
+                    bail!(
+                        "{} returned a web page ({}), not a hosts file, so it was not cached.",
+                        self.location,
+                        reason
+                    );
+                }
+                self.raw_list = body.lines().map(|l| l.to_string()).collect();
+                fetched = Some(body);
             }
         } else if Path::new(actualsrc).exists() {
-            // if it's a file
-            let file = File::open(actualsrc)
+            // if it's a file, perhaps compressed
+            let bytes = fs::read(actualsrc)
                 .unwrap_or_else(|_| panic!("Problem opening file: {}", actualsrc));
-            let buf = BufReader::new(file);
-            self.raw_list = buf
-                .lines()
-                .map(|l| l.expect("Could not parse line"))
-                .collect();
+            let text = decompress(bytes, None)?;
+            self.raw_list = text.lines().map(|l| l.to_string()).collect();
         } else {
-            // To Do: bomb out more gracefully
-            panic!("Shortcut, URL, or File \"{}\" does not exist.", actualsrc);
+            bail!("Shortcut, URL, or File \"{}\" does not exist.", actualsrc);
+        }
+        if self.format == InputFormat::Auto {
+            self.format = match get_source_spec_by_url(&self.location) {
+                Some(spec) if spec.format != InputFormat::Auto => spec.format,
+                _ => detect_format(&self.raw_list),
+            };
         }
         self.normalize();
 
+        if normalizedsrc.starts_with("http") {
+            if let Some(reason) = sniff_entries(self.domains.len(), self.invalid_entries.len()) {
+                // use none of it
+                self.domains.clear();
+                self.hosts.clear();
+                if let Some(cache_file) = cached {
+                    _ = fs::remove_file(&cache_file);
+                    bail!(
+                        "The cached copy of {} does not look like a hosts file ({}), so it was removed from the cache.",
+                        self.location,
+                        reason
+                    );
+                }
+                bail!(
+                    "{} does not look like a hosts file ({}), so it was not cached.",
+                    self.location,
+                    reason
+                );
+            }
+            if let Some(body) = fetched
+                && use_cache
+            {
+                // submit to cache
+                _ = cache::set(normalizedsrc, body, self.args.compresscache).await;
+            }
+        }
         Ok(())
     }
 
+    /// Parse the text of a source fetched by other means.
+    pub fn parse(&mut self, text: &str) {
+        self.raw_list = text.lines().map(|l| l.to_string()).collect();
+        if self.format == InputFormat::Auto {
+            self.format = detect_format(&self.raw_list);
+        }
+        self.normalize();
+    }
+
+    /// The report, in JSON, for `--json`.
+    pub fn report_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "location": self.location,
+            "domains": self.domains.len(),
+            "duplicates": self.duplicates,
+            "invalids": self.invalid_entries,
+            "rewrites": self.rewrites,
+            "redirects": self.redirects(),
+        })
+    }
+
+    /// The source parsed with its header, sections, and comments intact.
+    pub fn document(&self) -> Document {
+        Document::parse(&self.hosts_lines())
+    }
+
+    /// A single source has no table of sources in its report.
+    fn sources_table(&self) -> String {
+        String::new()
+    }
+
     fn normalize(&mut self) {
         self.trimlines();
         self.removeblanklines();
@@ -415,56 +649,88 @@ impl Hostssource {
         }
     }
 
+    /// The raw list, translated into hosts syntax when the source is in
+    /// another format.
+    fn hosts_lines(&self) -> Vec<String> {
+        match self.format {
+            InputFormat::Auto | InputFormat::Hosts => self.raw_list.clone(),
+            format => self
+                .raw_list
+                .iter()
+                .map(|l| to_hosts_line(format, l))
+                .collect(),
+        }
+    }
+
     fn trimlines(&mut self) {
-        let mut lines: Vec<String> = self.raw_list.clone();
+        let mut lines: Vec<String> = self.hosts_lines();
 
-        lines.iter_mut().for_each(|line| {
+        lines.iter_mut().enumerate().for_each(|(n, line)| {
             *line = norm_string(line.as_str());
             *line = trim_inline_comments(line.to_owned());
             if line.chars().count() > 0 && !self.domains.insert(line.to_owned()) {
                 self.duplicates.insert(line.to_owned());
             };
+            self.line_numbers.entry(line.to_owned()).or_insert(n + 1);
+        });
+    }
+
+    /// Record an entry we could not accept, with the line it came from.
+    fn invalid(&mut self, line: &str, entry: &str, reason: InvalidReason) {
+        let number = self.line_numbers.get(line).copied().unwrap_or(0);
+        let raw = match number {
+            0 => line.to_string(),
+            n => self.raw_list[n - 1].clone(),
+        };
+        self.invalids.insert(entry.to_string());
+        self.invalid_entries.push(InvalidEntry {
+            line: number,
+            raw,
+            entry: entry.to_string(),
+            reason,
         });
     }
 
     fn extract_domains(&mut self) {
         let mut domains_result: Domains = IndexSet::new();
-        // Domain aliases which are often found in hosts files which we do not want
-        // to flag as formally invalid.
-        let headertokens = vec![
-            "::1",
-            "broadcasthost",
-            "fe80::1%lo0",
-            "ff00::0",
-            "ff02::1",
-            "ff02::2",
-            "ff02::3",
-            "ip6-allhosts",
-            "ip6-allnodes",
-            "ip6-allrouters",
-            "ip6-localhost",
-            "ip6-localnet",
-            "ip6-loopback",
-            "ip6-mcastprefix",
-            "local",
-            "localhost",
-            "localhost.localdomain",
-        ];
-
-        for line in &self.domains {
-            for element in line.split_whitespace() {
-                if element != "0.0.0.0"
-                    && element != "127.0.0.1"
-                    && element != "255.255.255.255"
-                    && !headertokens.contains(&element)
+
+        let lines = std::mem::take(&mut self.domains);
+        for line in &lines {
+            let mut elements: Vec<&str> = line.split_whitespace().collect();
+            // the address, if any, leads the line
+            let ip_address = if elements.len() > 1 && elements[0].parse::<IpAddr>().is_ok() {
+                elements.remove(0)
+            } else {
+                ""
+            };
+            for element in elements {
+                // lowercase, without a trailing dot, and in punycode
+                let Some(domain) = canonical_domain(element) else {
+                    self.invalid(line, element, InvalidReason::IllegalCharacter);
+                    continue;
+                };
+                if domain != "0.0.0.0"
+                    && domain != "127.0.0.1"
+                    && domain != "255.255.255.255"
+                    && !HEADER_TOKENS.contains(&domain.as_str())
                 {
-                    if is_domain(element) {
-                        let unique = domains_result.insert(element.to_string());
-                        if !unique {
-                            self.duplicates.insert(element.to_string());
-                        }
+                    if !is_domain(&domain) {
+                        let reason =
+                            invalid_reason(&domain).unwrap_or(InvalidReason::IllegalCharacter);
+                        self.invalid(line, element, reason);
                     } else {
-                        self.invalids.insert(element.to_string());
+                        if domain != element {
+                            self.rewrites.insert(element.to_string(), domain.clone());
+                        }
+                        let unique = domains_result.insert(domain.clone());
+                        if unique {
+                            self.hosts.push(Host {
+                                ip_address: ip_address.to_string(),
+                                domain,
+                            });
+                        } else {
+                            self.duplicates.insert(domain);
+                        }
                     }
                 }
             }
@@ -477,7 +743,7 @@ impl Hostssource {
     }
 
     fn frontmatter(&mut self) {
-        for line in &self.raw_list {
+        for line in &self.hosts_lines() {
             if line.starts_with('#') {
                 self.front_matter.push(line.to_string());
             } else {
@@ -495,35 +761,266 @@ with_hosts_collection_shared_fields_and_impl!(
     #[derive(Default)]
     struct Amalgam {
         sources: Hostssources,
+        cross_duplicates: Domains,
+        source_pairs: SourcePairs,
     }
 );
 
 impl Amalgam {
     #[allow(dead_code)]
-    pub async fn new(locations: Vec<impl Into<String> + Clone>) -> Amalgam {
+    pub async fn new(locations: Vec<impl Into<String> + Clone>) -> anyhow::Result<Amalgam> {
+        Amalgam::with_args(locations, Arguments::default()).await
+    }
+
+    /// An amalgam whose sources are loaded with the given arguments, as
+    /// to skip the cache or fetch from a `--base-url`. Fails when any source
+    /// fails to load.
+    pub async fn with_args(
+        locations: Vec<impl Into<String> + Clone>,
+        args: Arguments,
+    ) -> anyhow::Result<Amalgam> {
         let mut amalgam: Amalgam = Amalgam {
             sources: Hostssources::new(),
             front_matter: vec![],
             domains: Domains::new(),
+            args: args.clone(),
             ..Default::default()
         };
+        // one fetcher, and one HTTP client, for every source
+        let fetcher = fetch::fetcher(&args)?;
+        let mut seen: HashSet<String> = HashSet::new();
         for l in locations {
-            let mut s = block_on(Hostssource::new(l.clone().into(), l.into()));
+            // a source listed twice is loaded, and counted, once
+            if !seen.insert(l.clone().into()) {
+                continue;
+            }
+            let mut s = Hostssource {
+                _name: l.clone().into(),
+                args: args.clone(),
+                ..Default::default()
+            };
+            let location: String = l.into();
+            s.load_with(&location, Some(fetcher.as_ref()))
+                .await
+                .with_context(|| format!("Unable to load {}.", location))?;
             amalgam.front_matter.append(&mut s.front_matter);
+            // domains an earlier source already supplied, by earlier source
+            let mut shared = vec![0; amalgam.sources.len()];
             for domain in s.domains.clone() {
-                amalgam.domains.insert(domain);
+                if amalgam.domains.contains(&domain) {
+                    for (j, earlier) in amalgam.sources.iter().enumerate() {
+                        if earlier.domains.contains(&domain) {
+                            shared[j] += 1;
+                        }
+                    }
+                    amalgam.cross_duplicates.insert(domain);
+                } else {
+                    amalgam.domains.insert(domain);
+                }
             }
+            for (j, domains) in shared.into_iter().enumerate() {
+                if domains > 0 {
+                    amalgam.source_pairs.push(SourcePair {
+                        a: amalgam.sources[j]._name.clone(),
+                        b: s._name.clone(),
+                        domains,
+                    });
+                }
+            }
+            amalgam.hosts.append(&mut s.hosts.clone());
+            amalgam.rewrites.extend(s.rewrites.clone());
             amalgam.raw_list.append(&mut s.raw_list.clone());
             amalgam.sources.push(s);
         }
-        amalgam
+        Ok(amalgam)
+    }
+
+    /// The size of every non-empty intersection pattern across the sources:
+    /// how many domains are in exactly {A, C}, in exactly {A, B, D}, and so on.
+    /// This is the data behind an UpSet plot. Largest first.
+    pub fn intersection_patterns(&self) -> Vec<IntersectionPattern> {
+        let mut membership: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (i, source) in self.sources.iter().enumerate() {
+            for domain in &source.domains {
+                // each source is in the amalgam once, and its domains are distinct
+                membership.entry(domain.as_str()).or_default().push(i);
+            }
+        }
+        let mut count: HashMap<Vec<usize>, usize> = HashMap::new();
+        for sources in membership.into_values() {
+            *count.entry(sources).or_insert(0) += 1;
+        }
+        let mut patterns: Vec<(Vec<usize>, usize)> = count.into_iter().collect();
+        patterns.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        patterns
+            .into_iter()
+            .map(|(sources, domains)| IntersectionPattern {
+                sources: sources
+                    .iter()
+                    .map(|i| self.sources[*i]._name.clone())
+                    .collect(),
+                domains,
+            })
+            .collect()
     }
+
+    /// Statistics for each of the amalgam's sources, including the domains
+    /// found in no other source, and the share of the amalgam each provides.
+    pub fn source_stats(&self) -> Vec<SourceStats> {
+        let mut membership: HashMap<&str, usize> = HashMap::new();
+        for source in &self.sources {
+            for domain in &source.domains {
+                *membership.entry(domain.as_str()).or_insert(0) += 1;
+            }
+        }
+        let mut supplied: HashSet<&str> = HashSet::new();
+        self.sources
+            .iter()
+            .map(|s| SourceStats {
+                location: s.location.clone(),
+                raw_lines: s.raw_list.len(),
+                domains: s.domains.len(),
+                duplicates: s.duplicates.len(),
+                invalids: s.invalids.len(),
+                unique: s
+                    .domains
+                    .iter()
+                    .filter(|d| membership.get(d.as_str()) == Some(&1))
+                    .count(),
+                redundant: s
+                    .domains
+                    .iter()
+                    .filter(|d| !supplied.insert(d.as_str()))
+                    .count(),
+                share: if self.domains.is_empty() {
+                    0.0
+                } else {
+                    s.domains.len() as f64 / self.domains.len() as f64
+                },
+            })
+            .collect()
+    }
+
+    /// A table of the sources' statistics, for the amalgam report.
+    fn sources_table(&self) -> String {
+        let mut out = format!(
+            "Sources:\n{:>10}  {:>10}  {:>10}  {:>10}  {:>10}  {:>10}  {:>7}  {}\n",
+            "Lines",
+            "Domains",
+            "Duplicates",
+            "Invalids",
+            "Unique",
+            "Redundant",
+            "Share",
+            "Location"
+        );
+        for s in self.source_stats() {
+            out.push_str(&format!(
+                "{:>10}  {:>10}  {:>10}  {:>10}  {:>10}  {:>10}  {:>6.1}%  {}\n",
+                s.raw_lines.to_formatted_string(&Locale::en),
+                s.domains.to_formatted_string(&Locale::en),
+                s.duplicates.to_formatted_string(&Locale::en),
+                s.invalids.to_formatted_string(&Locale::en),
+                s.unique.to_formatted_string(&Locale::en),
+                s.redundant.to_formatted_string(&Locale::en),
+                s.share * 100.0,
+                s.location
+            ));
+        }
+        out.push_str(&format!(
+            "Domains in more than one source: {}\n",
+            self.cross_duplicates.len().to_formatted_string(&Locale::en)
+        ));
+        if self.args.showduplicates {
+            for pair in &self.source_pairs {
+                out.push_str(&format!(
+                    "{:>10}  {} & {}\n",
+                    pair.domains.to_formatted_string(&Locale::en),
+                    pair.a,
+                    pair.b
+                ));
+            }
+        }
+        out
+    }
+}
+
+/// Domains which two sources of an amalgam both list, where `a` precedes `b`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct SourcePair {
+    pub a: String,
+    pub b: String,
+    pub domains: usize,
+}
+
+pub type SourcePairs = Vec<SourcePair>;
+
+/// Domains found in exactly a given set of sources, and in no others.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct IntersectionPattern {
+    pub sources: Vec<String>,
+    pub domains: usize,
+}
+
+/// Statistics about one source of a built product.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SourceStats {
+    pub location: String,
+    pub raw_lines: usize,
+    pub domains: usize,
+    pub duplicates: usize,
+    pub invalids: usize,
+    /// Domains found in no other source.
+    pub unique: usize,
+    /// Domains an earlier source of the amalgam already supplied.
+    pub redundant: usize,
+    /// The fraction of the amalgam's domains found in this source.
+    pub share: f64,
+}
+
+/// Statistics about a built product.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ProductStats {
+    pub name: String,
+    pub tags: Tags,
+    pub destination: String,
+    pub domains: usize,
+    /// Change in domain count since the previous build.
+    pub delta: i64,
+    /// Domains listed by more than one source.
+    #[serde(default)]
+    pub cross_duplicates: usize,
+    pub sources: Vec<SourceStats>,
+}
+
+/// Arguments which serve sources from the test fixtures.
+#[cfg(test)]
+fn fixture_args() -> Arguments {
+    Arguments {
+        base_url: Some(crate::fetch::FIXTURES.to_string()),
+        ..Default::default()
+    }
+}
+
+/// A local hosts file, the fixture standing in for the base hosts.
+#[cfg(test)]
+fn fixture_file() -> String {
+    format!(
+        "{}/raw.githubusercontent.com/StevenBlack/hosts/master/hosts",
+        crate::fetch::FIXTURES
+    )
 }
 
 #[async_std::test]
 async fn test_create_amalgam_with_lists_has_domains() {
     use thousands::Separable;
-    let a = Amalgam::new(vec!["stevenblack", "mvps", "yoyo", "someonewhocares"]).await;
+    let a = Amalgam::with_args(
+        vec!["stevenblack", "mvps", "adaway", "someonewhocares"],
+        fixture_args(),
+    )
+    .await
+    .unwrap();
     let mut tally: usize = 0;
     for s in a.sources {
         tally += s.domains.len();
@@ -546,8 +1043,12 @@ async fn test_create_amalgam_with_lists_has_domains() {
 
 #[async_std::test]
 async fn test_create_amalgam_with_duplicate_lists_does_not_double_count_domains() {
-    let a = Amalgam::new(vec!["stevenblack"]).await;
-    let b = Amalgam::new(vec!["stevenblack", "stevenblack"]).await;
+    let a = Amalgam::with_args(vec!["stevenblack"], fixture_args())
+        .await
+        .unwrap();
+    let b = Amalgam::with_args(vec!["stevenblack", "stevenblack"], fixture_args())
+        .await
+        .unwrap();
     assert_eq!(
         a.domains.len(),
         b.domains.len(),
@@ -558,7 +1059,12 @@ async fn test_create_amalgam_with_duplicate_lists_does_not_double_count_domains(
 #[async_std::test]
 async fn test_create_amalgam_with_shortcuts_has_domains() {
     use thousands::Separable;
-    let a = Amalgam::new(vec!["base", "p", "p-only", "g", "g-only", "fgps"]).await;
+    let a = Amalgam::with_args(
+        vec!["base", "p", "p-only", "g", "g-only", "fgps"],
+        fixture_args(),
+    )
+    .await
+    .unwrap();
     let mut tally: usize = 0;
     for s in a.sources {
         tally += s.domains.len();
@@ -600,17 +1106,16 @@ mod tests {
         assert!(handle.await.is_ok(), "Expected task group to be ok");
     }
 
-    // ToDo: skip this test if the folder and file do not exist
     #[test]
     fn test_hostssource_load_from_file_has_domains() {
         let mut s = Hostssource {
             ..Default::default()
         };
-        // ignore the result of this load for now
-        _ = block_on(s.load("/Users/Steve/Dropbox/dev/hosts/hosts"));
+        block_on(s.load(&fixture_file())).unwrap();
         assert_eq!(
-            s.location, "/Users/Steve/Dropbox/dev/hosts/hosts",
-            "Loading from local file, expected location to be /Users/Steve/Dropbox/dev/hosts/hosts"
+            s.location,
+            fixture_file(),
+            "Loading from local file, expected location to be the file"
         );
         assert!(
             s.front_matter.len() > 0,
@@ -626,16 +1131,13 @@ mod tests {
         );
     }
 
-    // ToDo: skip this test if the folder and file do not exist
     #[test]
     fn test_hostssource_new_from_file_has_domains() {
-        let s = block_on(Hostssource::new(
-            "/Users/Steve/Dropbox/dev/hosts/hosts",
-            "arbitrary name",
-        ));
+        let s = block_on(Hostssource::new(fixture_file(), "arbitrary name"));
         assert_eq!(
-            s.location, "/Users/Steve/Dropbox/dev/hosts/hosts",
-            "Loading from local file, expected location to be /Users/Steve/Dropbox/dev/hosts/hosts"
+            s.location,
+            fixture_file(),
+            "Loading from local file, expected location to be the file"
         );
         assert!(
             s.front_matter.len() > 0,
@@ -662,11 +1164,11 @@ mod tests {
     #[test]
     fn test_hostssource_load_from_github_has_domains() {
         let mut s = Hostssource {
+            args: fixture_args(),
             ..Default::default()
         };
         let url = "https://raw.githubusercontent.com/StevenBlack/hosts/f5d5efab/data/URLHaus/hosts";
-        // ignore the result of this load for now
-        _ = block_on(s.load(&url));
+        block_on(s.load(url)).unwrap();
         assert_eq!(
             s.location,
             url.to_string(),
@@ -677,23 +1179,24 @@ mod tests {
             "Loading from GitHub, expected front matter length to be greater than 4"
         );
         assert!(
-            s.raw_list.len() > 1000,
-            "Loading from GitHub, expected raw list length to be greater than 1,000"
+            s.raw_list.len() > 10,
+            "Loading from GitHub, expected raw list length to be greater than 10"
         );
-        assert!(
-            s.domains.len() > 1000,
-            "Loading from GitHub, expected number of domains to be greater than 1,000"
+        assert_eq!(
+            s.domains.len(),
+            10,
+            "Loading from GitHub, expected the fixture's 10 domains"
         );
     }
 
     #[test]
     fn test_hostssource_load_big_file_from_github_has_domains() {
         let mut s = Hostssource {
+            args: fixture_args(),
             ..Default::default()
         };
         let url = "https://raw.githubusercontent.com/StevenBlack/hosts/master/hosts";
-        // ignore the result of this load for now
-        _ = block_on(s.load(&url));
+        block_on(s.load(url)).unwrap();
         assert_eq!(
             s.location,
             url.to_string(),
@@ -716,10 +1219,10 @@ mod tests {
     #[test]
     fn test_hostssource_load_from_shortcut_has_domains() {
         let mut s = Hostssource {
+            args: fixture_args(),
             ..Default::default()
         };
-        // ignore the result of this load for now
-        _ = block_on(s.load("base"));
+        block_on(s.load("base")).unwrap();
         assert_eq!(
             s.location, "https://raw.githubusercontent.com/StevenBlack/hosts/master/hosts",
             "When using a shortcut, expected location to and URL to be the same"
@@ -939,6 +1442,354 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_hostssource_load_retains_addresses_and_flags_redirects() {
+        let mut s = Hostssource {
+            ..Default::default()
+        };
+        block_on(s.load(
+            r##"
+            0.0.0.0 example.com
+            127.0.0.1 example.org
+            93.184.216.34 www.example.com
+            example.net
+            "##,
+        ))
+        .unwrap();
+        assert_eq!(s.domains.len(), 4);
+        assert_eq!(s.invalids.len(), 0);
+        assert_eq!(
+            s.hosts[2],
+            Host {
+                ip_address: "93.184.216.34".to_string(),
+                domain: "www.example.com".to_string()
+            }
+        );
+        let redirects = s.redirects();
+        assert_eq!(
+            redirects.len(),
+            1,
+            "Expected only one redirect, but got: {:?}",
+            redirects
+        );
+        assert_eq!(redirects[0].domain, "www.example.com");
+    }
+
+    #[test]
+    fn test_covered_subdomains_are_those_with_a_blocked_parent() {
+        let mut s = Hostssource {
+            ..Default::default()
+        };
+        block_on(s.load(
+            r##"
+            0.0.0.0 example.com
+            0.0.0.0 ads.example.com
+            0.0.0.0 a.b.example.com
+            0.0.0.0 ads.example.org
+            0.0.0.0 notexample.com
+            "##,
+        ))
+        .unwrap();
+        let expected: Domains =
+            IndexSet::from(["ads.example.com".to_string(), "a.b.example.com".to_string()]);
+        assert_eq!(s.covered_subdomains(), expected);
+    }
+
+    #[test]
+    fn test_tallies_use_the_public_suffix_list_unless_naive() {
+        let mut s = Hostssource {
+            ..Default::default()
+        };
+        block_on(s.load(
+            r##"
+            0.0.0.0 www.foo.co.uk
+            0.0.0.0 ads.foo.co.uk
+            0.0.0.0 bar.co.uk
+            "##,
+        ))
+        .unwrap();
+        assert_eq!(
+            s.rootdomains(),
+            vec![("foo.co.uk".to_string(), 2), ("bar.co.uk".to_string(), 1)]
+        );
+        assert_eq!(s.tld(), vec![("co.uk".to_string(), 3)]);
+        s.args.naive = true;
+        assert_eq!(s.rootdomains(), vec![("co.uk".to_string(), 3)]);
+        assert_eq!(s.tld(), vec![("uk".to_string(), 3)]);
+    }
+
+    #[test]
+    fn test_hostssource_load_canonicalizes_domains_and_records_rewrites() {
+        let mut s = Hostssource {
+            ..Default::default()
+        };
+        block_on(s.load(
+            r##"
+            0.0.0.0 Example.COM
+            0.0.0.0 example.com.
+            0.0.0.0 bücher.example
+            0.0.0.0 xn--bcher-kva.example
+            "##,
+        ))
+        .unwrap();
+        let expected_domains: IndexSet<String> = IndexSet::from([
+            "example.com".to_string(),
+            "xn--bcher-kva.example".to_string(),
+        ]);
+        assert_eq!(
+            s.domains, expected_domains,
+            "Expected domains to be identical, but got: {:?} expected: {:?}",
+            s.domains, expected_domains
+        );
+        assert_eq!(s.duplicates.len(), 2);
+        assert_eq!(s.rewrites.len(), 3);
+        assert_eq!(
+            s.rewrites.get("bücher.example"),
+            Some(&"xn--bcher-kva.example".to_string())
+        );
+    }
+
+    #[test]
+    fn test_hostssource_load_from_adblock_text_has_domains() {
+        let mut s = Hostssource {
+            ..Default::default()
+        };
+        block_on(s.load(
+            r##"
+            [Adblock Plus 2.0]
+            ! Title: test
+            ||example.com^
+            ||www.example.com^$important
+            @@||example.org^
+            example.net##.banner
+            "##,
+        ))
+        .unwrap();
+        assert_eq!(s.format, InputFormat::Adblock);
+        assert_eq!(
+            s.front_matter.len(),
+            2,
+            "Expected front matter length to be 2, but got: {}",
+            s.front_matter.len()
+        );
+        let expected_domains: IndexSet<String> =
+            IndexSet::from(["example.com".to_string(), "www.example.com".to_string()]);
+        assert_eq!(
+            s.domains, expected_domains,
+            "Expected domains to be identical, but got: {:?} expected: {:?}",
+            s.domains, expected_domains
+        );
+        assert_eq!(
+            s.invalids.len(),
+            0,
+            "Expected the number of invalids to be 0, but got: {}",
+            s.invalids.len()
+        );
+    }
+
+    #[test]
+    fn test_hostssource_load_records_invalid_entries_with_lines_and_reasons() {
+        let mut s = Hostssource {
+            ..Default::default()
+        };
+        block_on(s.load(&format!(
+            r##"
+            # invalid entries
+            0.0.0.0 example.com
+            0.0.0.0 intranet
+            0.0.0.0   ads..example.com  # an empty label
+            0.0.0.0 {}.com
+            "##,
+            "a".repeat(64)
+        )))
+        .unwrap();
+        assert_eq!(s.domains.len(), 1);
+        assert_eq!(
+            s.invalid_entries.len(),
+            3,
+            "Expected 3 invalid entries, but got: {:?}",
+            s.invalid_entries
+        );
+        assert_eq!(
+            s.invalid_entries[0],
+            InvalidEntry {
+                line: 3,
+                raw: "0.0.0.0 intranet".to_string(),
+                entry: "intranet".to_string(),
+                reason: InvalidReason::NoDot,
+            }
+        );
+        assert_eq!(s.invalid_entries[1].line, 4);
+        assert_eq!(
+            s.invalid_entries[1].raw,
+            "0.0.0.0   ads..example.com  # an empty label"
+        );
+        assert_eq!(s.invalid_entries[1].reason, InvalidReason::EmptyLabel);
+        assert_eq!(s.invalid_entries[2].reason, InvalidReason::LabelTooLong);
+        let json = s.report_json();
+        assert_eq!(json["invalids"][0]["reason"], "no-dot");
+        assert_eq!(json["invalids"][2]["line"], 5);
+    }
+
+    #[async_std::test]
+    async fn test_hostssource_load_from_fixture_has_domains() {
+        let mut s = Hostssource {
+            args: fixture_args(),
+            ..Default::default()
+        };
+        let url = "https://raw.githubusercontent.com/StevenBlack/hosts/f5d5efab/data/URLHaus/hosts";
+        s.load(url).await.unwrap();
+        assert_eq!(s.location, url);
+        assert_eq!(s.front_matter.len(), 6);
+        assert_eq!(s.domains.len(), 10);
+        assert!(s.domains.contains("c2.example.org"));
+    }
+
+    #[async_std::test]
+    async fn test_hostssource_load_refuses_a_web_page() {
+        let mut s = Hostssource {
+            args: fixture_args(),
+            ..Default::default()
+        };
+        let error = s.load("https://example.com/error-page").await.unwrap_err();
+        assert!(error.to_string().contains("<!doctype> markup"), "{}", error);
+        assert!(s.domains.is_empty());
+        assert!(
+            s.load("https://example.com/missing").await.is_err(),
+            "Expected a missing fixture to fail to load"
+        );
+    }
+
+    #[async_std::test]
+    async fn test_hostssource_load_removes_a_bad_cached_copy() {
+        let url = format!("https://rh-test.invalid/garbage-{}", std::process::id());
+        let garbage: String = (0..20)
+            .map(|i| format!("0.0.0.0 intranet{}\n", i))
+            .collect();
+        cache::set(url.clone(), garbage, false).await.unwrap();
+        let mut s = Hostssource {
+            ..Default::default()
+        };
+        let error = s.load(&url).await.unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("so it was removed from the cache"),
+            "{}",
+            error
+        );
+        assert!(cache::get(url).await.is_none());
+    }
+
+    #[async_std::test]
+    async fn test_amalgam_with_args_loads_fixtures() {
+        let a = Amalgam::with_args(
+            vec![
+                "https://raw.githubusercontent.com/StevenBlack/hosts/f5d5efab/data/URLHaus/hosts",
+                "https://example.com/hosts",
+            ],
+            fixture_args(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(a.sources.len(), 2);
+        assert_eq!(a.domains.len(), 11);
+        assert_eq!(a.cross_duplicates.len(), 1);
+    }
+
+    #[async_std::test]
+    async fn test_amalgam_source_stats_report_each_source() {
+        let a = Amalgam::new(vec![
+            "# a\n0.0.0.0 one.com\n0.0.0.0 two.com\n0.0.0.0 two.com\n0.0.0.0 not_a_domain",
+            "0.0.0.0 two.com\n0.0.0.0 three.com",
+        ])
+        .await
+        .unwrap();
+        let stats = a.source_stats();
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].raw_lines, 5);
+        assert_eq!(stats[0].domains, 2);
+        assert_eq!(stats[0].duplicates, 1);
+        assert_eq!(stats[0].invalids, 1);
+        assert_eq!(stats[0].unique, 1);
+        assert_eq!(stats[1].unique, 1);
+        assert!((stats[0].share - 2.0 / 3.0).abs() < 1e-9);
+        let report = a.to_string();
+        assert!(report.contains("Sources:"), "{}", report);
+        assert!(report.contains(" 66.7%  "), "{}", report);
+    }
+
+    #[async_std::test]
+    async fn test_amalgam_tracks_duplicates_across_sources_by_pair() {
+        let a = Amalgam::new(vec![
+            "0.0.0.0 one.com\n0.0.0.0 two.com\n0.0.0.0 two.com",
+            "0.0.0.0 two.com\n0.0.0.0 three.com",
+            "0.0.0.0 two.com\n0.0.0.0 three.com\n0.0.0.0 four.com",
+        ])
+        .await
+        .unwrap();
+        assert_eq!(a.domains.len(), 4);
+        // within-source duplicates are the sources' own
+        assert_eq!(a.duplicates.len(), 0);
+        assert_eq!(a.sources[0].duplicates.len(), 1);
+        assert_eq!(a.cross_duplicates.len(), 2);
+        let pair = |a_index: usize, b_index: usize, domains: usize| SourcePair {
+            a: a.sources[a_index]._name.clone(),
+            b: a.sources[b_index]._name.clone(),
+            domains,
+        };
+        assert_eq!(
+            a.source_pairs,
+            vec![pair(0, 1, 1), pair(0, 2, 1), pair(1, 2, 2)]
+        );
+        let redundant: Vec<usize> = a.source_stats().iter().map(|s| s.redundant).collect();
+        assert_eq!(redundant, vec![0, 1, 2]);
+    }
+
+    #[async_std::test]
+    async fn test_amalgam_intersection_patterns_are_exact() {
+        let a = Amalgam::new(vec![
+            "0.0.0.0 one.com\n0.0.0.0 two.com\n0.0.0.0 three.com",
+            "0.0.0.0 two.com\n0.0.0.0 three.com\n0.0.0.0 four.com",
+            "0.0.0.0 three.com\n0.0.0.0 five.com\n0.0.0.0 six.com",
+        ])
+        .await
+        .unwrap();
+        let patterns: Vec<(usize, usize)> = a
+            .intersection_patterns()
+            .iter()
+            .map(|p| (p.sources.len(), p.domains))
+            .collect();
+        // {C}: 2, {A}: 1, {B}: 1, {A, B}: 1, {A, B, C}: 1
+        assert_eq!(
+            patterns,
+            vec![(1, 2), (1, 1), (2, 1), (3, 1), (1, 1)],
+            "Expected exact intersection patterns, but got: {:?}",
+            patterns
+        );
+        let total: usize = a.intersection_patterns().iter().map(|p| p.domains).sum();
+        assert_eq!(total, a.domains.len());
+    }
+
+    #[async_std::test]
+    async fn test_amalgam_loads_a_source_listed_twice_once() {
+        let a = Amalgam::new(vec![
+            "0.0.0.0 one.com\n0.0.0.0 two.com",
+            "0.0.0.0 two.com\n0.0.0.0 three.com",
+            "0.0.0.0 one.com\n0.0.0.0 two.com",
+        ])
+        .await
+        .unwrap();
+        assert_eq!(a.sources.len(), 2);
+        assert_eq!(a.cross_duplicates.len(), 1);
+        let patterns: Vec<(usize, usize)> = a
+            .intersection_patterns()
+            .iter()
+            .map(|p| (p.sources.len(), p.domains))
+            .collect();
+        assert_eq!(patterns, vec![(1, 1), (2, 1), (1, 1)]);
+    }
+
     #[test]
     fn test_domains_type_inserting_duplicates_does_not_produce_duplicate_domains() {
         let mut d = Domains::new();
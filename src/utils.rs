@@ -1,10 +1,14 @@
 #![allow(dead_code)]
 /// Utilities and functions
 ///
+use addr::error::Kind;
 use addr::parser::DnsName;
 use psl::List;
+use serde::Serialize;
 use std::collections::hash_map::DefaultHasher;
+use std::fmt;
 use std::hash::{Hash, Hasher};
+use std::net::IpAddr;
 
 pub fn is_domain(s: &str) -> bool {
     // parse_dns_name(s).is_ok()
@@ -14,6 +18,72 @@ pub fn is_domain(s: &str) -> bool {
     List.parse_dns_name(s).is_ok()
 }
 
+/// Why an entry is not a domain we can block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum InvalidReason {
+    NoDot,
+    EmptyLabel,
+    LabelTooLong,
+    TooLong,
+    IllegalCharacter,
+    IpAddress,
+}
+
+impl fmt::Display for InvalidReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            InvalidReason::NoDot => "no dot",
+            InvalidReason::EmptyLabel => "empty label",
+            InvalidReason::LabelTooLong => "label too long",
+            InvalidReason::TooLong => "total too long",
+            InvalidReason::IllegalCharacter => "illegal character",
+            InvalidReason::IpAddress => "IP address in domain column",
+        };
+        write!(f, "{}", reason)
+    }
+}
+
+/// The reason an entry is not a valid domain, or `None` when it is one. It
+/// agrees with `is_domain`, which decides what is extracted.
+pub fn invalid_reason(s: &str) -> Option<InvalidReason> {
+    if s.parse::<IpAddr>().is_ok() {
+        return Some(InvalidReason::IpAddress);
+    }
+    if !s.contains('.') {
+        return Some(InvalidReason::NoDot);
+    }
+    if let Err(e) = List.parse_dns_name(s) {
+        return Some(match e.kind() {
+            Kind::LabelTooLong => InvalidReason::LabelTooLong,
+            Kind::NameTooLong => InvalidReason::TooLong,
+            Kind::EmptyLabel | Kind::EmptyName => InvalidReason::EmptyLabel,
+            _ => InvalidReason::IllegalCharacter,
+        });
+    }
+    None
+}
+
+#[test]
+fn test_invalid_reason_function_explains_why_entries_are_not_domains() {
+    assert_eq!(invalid_reason("github.com"), None);
+    assert_eq!(invalid_reason("localhost"), Some(InvalidReason::NoDot));
+    assert_eq!(invalid_reason("a..com"), Some(InvalidReason::EmptyLabel));
+    assert_eq!(
+        invalid_reason(&("a".repeat(64) + ".com")),
+        Some(InvalidReason::LabelTooLong)
+    );
+    assert_eq!(
+        invalid_reason(&(("a".repeat(60) + ".").repeat(5) + "com")),
+        Some(InvalidReason::TooLong)
+    );
+    assert_eq!(invalid_reason("1.2.3.4"), Some(InvalidReason::IpAddress));
+    for s in ["example.zzzz", "localhost", "a..com", "ads|example.com"] {
+        assert_eq!(invalid_reason(s).is_none(), is_domain(s), "{}", s);
+    }
+    assert_eq!(InvalidReason::TooLong.to_string(), "total too long");
+}
+
 #[test]
 fn test_is_domaain_function_handles_good_and_bad_domains() {
     assert_eq!(is_domain("localhost"), false);
@@ -62,6 +132,146 @@ fn test_is_domaain_function_handles_domains_that_are_too_long() {
     );
 }
 
+/// Whether an address sends traffic nowhere, as blocklist entries should.
+/// Entries with no address, as in plain domain lists, count as null routes,
+/// as does the old `255.255.255.255` blackhole.
+pub fn is_null_route(ip: &str) -> bool {
+    if ip.is_empty() {
+        return true;
+    }
+    match ip.parse::<IpAddr>() {
+        Ok(IpAddr::V4(addr)) if addr.is_broadcast() => true,
+        Ok(addr) => addr.is_unspecified() || addr.is_loopback(),
+        Err(_) => false,
+    }
+}
+
+#[test]
+fn test_is_null_route_function_handles_null_and_real_addresses() {
+    assert!(is_null_route(""));
+    assert!(is_null_route("0.0.0.0"));
+    assert!(is_null_route("127.0.0.1"));
+    assert!(is_null_route("::"));
+    assert!(is_null_route("::1"));
+    assert!(is_null_route("255.255.255.255"));
+    assert!(!is_null_route("255.255.255.254"));
+    assert!(!is_null_route("1.2.3.4"));
+    assert!(!is_null_route("2606:4700::1111"));
+}
+
+/// Parse an IPv4 or IPv6 address given on the command line.
+pub fn parse_ip_address(s: &str) -> Result<String, String> {
+    s.parse::<IpAddr>()
+        .map(|ip| ip.to_string())
+        .map_err(|_| format!("\"{}\" is not an IPv4 or IPv6 address", s))
+}
+
+#[test]
+fn test_parse_ip_address_function_accepts_only_addresses() {
+    assert_eq!(parse_ip_address("0.0.0.0"), Ok("0.0.0.0".to_string()));
+    assert_eq!(parse_ip_address("::"), Ok("::".to_string()));
+    assert_eq!(parse_ip_address("0:0:0:0:0:0:0:0"), Ok("::".to_string()));
+    assert!(parse_ip_address("localhost").is_err());
+    assert!(parse_ip_address("0.0.0.0.0").is_err());
+}
+
+/// The registrable domain, like `foo.co.uk` for `www.foo.co.uk`, from the
+/// Public Suffix List. With `naive`, the last two labels instead.
+pub fn root_domain(domain: &str, naive: bool) -> Option<String> {
+    let domain = domain.to_lowercase();
+    if !naive && let Some(root) = psl::domain_str(&domain) {
+        return Some(root.to_string());
+    }
+    let parts: Vec<&str> = domain.split('.').collect();
+    if parts.len() >= 2 {
+        Some(format!(
+            "{}.{}",
+            parts[parts.len() - 2],
+            parts[parts.len() - 1]
+        ))
+    } else {
+        None
+    }
+}
+
+/// The effective top level domain, like `co.uk` for `www.foo.co.uk`, from the
+/// Public Suffix List. With `naive`, the last label instead.
+pub fn effective_tld(domain: &str, naive: bool) -> Option<String> {
+    let domain = domain.to_lowercase();
+    if !naive && let Some(suffix) = psl::suffix_str(&domain) {
+        return Some(suffix.to_string());
+    }
+    domain.rsplit('.').next().map(|tld| tld.to_string())
+}
+
+#[test]
+fn test_root_domain_and_tld_functions_follow_the_public_suffix_list() {
+    assert_eq!(
+        root_domain("www.foo.co.uk", false),
+        Some("foo.co.uk".to_string())
+    );
+    assert_eq!(
+        root_domain("www.foo.co.uk", true),
+        Some("co.uk".to_string())
+    );
+    assert_eq!(
+        root_domain("bar.github.io", false),
+        Some("bar.github.io".to_string())
+    );
+    assert_eq!(
+        root_domain("bar.github.io", true),
+        Some("github.io".to_string())
+    );
+    assert_eq!(
+        root_domain("WWW.Example.COM", false),
+        Some("example.com".to_string())
+    );
+    assert_eq!(
+        effective_tld("www.foo.co.uk", false),
+        Some("co.uk".to_string())
+    );
+    assert_eq!(effective_tld("www.foo.co.uk", true), Some("uk".to_string()));
+    assert_eq!(effective_tld("example.com", false), Some("com".to_string()));
+}
+
+/// The canonical form of a domain: lowercase, without a trailing dot, and in
+/// ASCII, with internationalized labels in punycode. `None` when the domain
+/// cannot be converted to ASCII.
+pub fn canonical_domain(s: &str) -> Option<String> {
+    let trimmed = s.strip_suffix('.').unwrap_or(s);
+    if trimmed.is_ascii() {
+        Some(trimmed.to_ascii_lowercase())
+    } else {
+        idna::domain_to_ascii(trimmed).ok()
+    }
+}
+
+/// The Unicode form of a domain, with punycode labels decoded.
+pub fn unicode_domain(s: &str) -> String {
+    idna::domain_to_unicode(s).0
+}
+
+#[test]
+fn test_canonical_domain_function_lowercases_trims_and_encodes() {
+    assert_eq!(
+        canonical_domain("Example.COM."),
+        Some("example.com".to_string())
+    );
+    assert_eq!(
+        canonical_domain("bücher.example"),
+        Some("xn--bcher-kva.example".to_string())
+    );
+    assert_eq!(
+        canonical_domain("BÜCHER.example"),
+        Some("xn--bcher-kva.example".to_string())
+    );
+    assert_eq!(
+        canonical_domain("xn--bcher-kva.example"),
+        Some("xn--bcher-kva.example".to_string())
+    );
+    assert_eq!(unicode_domain("xn--bcher-kva.example"), "bücher.example");
+}
+
 #[allow(dead_code)]
 pub fn print_type_of<T>(_: &T) {
     println!("===> {}", std::any::type_name::<T>())